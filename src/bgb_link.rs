@@ -0,0 +1,309 @@
+//! Link cable speaking the BGB 1.4 network protocol, so this emulator can be
+//! linked with BGB and other emulators that support it.
+//!
+//! Every packet is 8 bytes: a command, three data bytes and a little-endian
+//! 31-bit timestamp counting at 2MiHz. Both sides report their time with
+//! `sync3` packets, and neither runs further than `MAX_AHEAD` past the other.
+//! Transfers clocked by the other side are applied once emulated time
+//! reaches their timestamp.
+
+use super::interrupts::InterruptController;
+use super::serial::{LinkCable, SerialLink};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+const VERSION: u8 = 1;
+const SYNC1: u8 = 104;
+const SYNC2: u8 = 105;
+const SYNC3: u8 = 106;
+const STATUS: u8 = 108;
+const WANT_DISCONNECT: u8 = 109;
+
+const STATUS_RUNNING: u8 = 0x01;
+
+const TIMESTAMP_MASK: u32 = 0x7fff_ffff;
+
+/// How far this side may run past the other's last reported time, in 2MiHz
+/// ticks. About a frame.
+const MAX_AHEAD: u32 = 35_000;
+
+/// How long to wait for the other side before giving up on it.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Packet {
+    command: u8,
+    data: [u8; 3],
+    timestamp: u32,
+}
+
+impl Packet {
+    fn new(command: u8, b2: u8, b3: u8, b4: u8, timestamp: u32) -> Self {
+        Packet { command, data: [b2, b3, b4], timestamp: timestamp & TIMESTAMP_MASK }
+    }
+
+    fn to_bytes(self) -> [u8; 8] {
+        let t = self.timestamp.to_le_bytes();
+        [self.command, self.data[0], self.data[1], self.data[2], t[0], t[1], t[2], t[3]]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Packet {
+            command: bytes[0],
+            data: [bytes[1], bytes[2], bytes[3]],
+            timestamp: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) & TIMESTAMP_MASK,
+        }
+    }
+}
+
+/// Whether timestamp `a` is at or after `b`, allowing for wrapping.
+fn at_or_after(a: u32, b: u32) -> bool {
+    a.wrapping_sub(b) & TIMESTAMP_MASK < 0x4000_0000
+}
+
+/// Converts the emulator's 8MHz link time to a 2MiHz timestamp, which wraps
+/// at 2^31.
+fn timestamp(time: u64) -> u32 {
+    (time / 4) as u32 & TIMESTAMP_MASK
+}
+
+pub struct BgbLink {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    /// Transfers clocked by the other side: data and timestamp.
+    transfers: VecDeque<(u8, u32)>,
+    reply: Option<u8>,
+    stale_replies: usize,
+    remote_time: u32,
+    local_time: u32,
+    polls: u32,
+}
+
+impl BgbLink {
+    /// Hosts a link on `127.0.0.1:port`, waiting for a client to connect.
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for a BGB link client on 127.0.0.1:{}", port);
+        let (stream, address) = listener.accept()?;
+        println!("BGB link client connected from {}", address);
+        BgbLink::new(stream)
+    }
+
+    /// Connects to a host such as BGB's "listen" mode.
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        println!("BGB link connected to {}", stream.peer_addr()?);
+        BgbLink::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let mut link = BgbLink {
+            stream,
+            buffer: vec![],
+            transfers: VecDeque::new(),
+            reply: None,
+            stale_replies: 0,
+            remote_time: 0,
+            local_time: 0,
+            polls: 0,
+        };
+
+        link.send_packet(Packet::new(VERSION, 1, 4, 0, 0))?;
+        let deadline = Instant::now() + TIMEOUT;
+        while link.buffer.len() < 8 && Instant::now() < deadline {
+            link.fill(Some(deadline - Instant::now()))?;
+        }
+        if link.next_packet() != Some(Packet::new(VERSION, 1, 4, 0, 0)) {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a BGB 1.4 link"));
+        }
+        link.send_packet(Packet::new(STATUS, STATUS_RUNNING, 0, 0, 0))?;
+        Ok(link)
+    }
+
+    fn send_packet(&mut self, packet: Packet) -> io::Result<()> {
+        self.stream.write_all(&packet.to_bytes())
+    }
+
+    fn send_time(&mut self) -> io::Result<()> {
+        self.send_packet(Packet::new(SYNC3, 0, 0, 0, self.local_time))
+    }
+
+    /// Reads what has arrived, waiting up to `timeout` for at least one byte
+    /// if given.
+    fn fill(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_nonblocking(timeout.is_none())?;
+        self.stream.set_read_timeout(timeout)?;
+        let mut bytes = [0; 64];
+        let result = self.stream.read(&mut bytes);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(count) => {
+                self.buffer.extend_from_slice(&bytes[..count]);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn next_packet(&mut self) -> Option<Packet> {
+        if self.buffer.len() < 8 {
+            return None;
+        }
+        let packet = Packet::from_bytes(&self.buffer[..8]);
+        self.buffer.drain(..8);
+        Some(packet)
+    }
+
+    /// Handles everything except transfers, which are queued.
+    fn handle(&mut self, packet: Packet) -> io::Result<()> {
+        match packet.command {
+            SYNC1 => {
+                self.transfers.push_back((packet.data[0], packet.timestamp));
+                self.remote_time = packet.timestamp;
+            }
+            SYNC2 => self.take_reply(packet.data[0]),
+            // The other side wasn't waiting on an external clock
+            SYNC3 if packet.data[0] == 1 => self.take_reply(0xff),
+            SYNC3 => self.remote_time = packet.timestamp,
+            STATUS => self.send_time()?,
+            WANT_DISCONNECT => {
+                return Err(io::Error::new(ErrorKind::ConnectionAborted, "the other side disconnected"));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn take_reply(&mut self, byte: u8) {
+        if self.stale_replies > 0 {
+            self.stale_replies -= 1;
+        } else {
+            self.reply = Some(byte);
+        }
+    }
+
+    /// Answers transfers clocked by the other side once emulated time has
+    /// caught up with them.
+    fn apply_transfers(&mut self, serial: &mut SerialLink, interrupts: &mut InterruptController) -> io::Result<()> {
+        while let Some(&(byte, at)) = self.transfers.front() {
+            if !at_or_after(self.local_time, at) {
+                break;
+            }
+            self.transfers.pop_front();
+            let reply = match serial.external_transfer(byte, interrupts) {
+                Some(reply) => Packet::new(SYNC2, reply, 0x80, 0, 0),
+                None => Packet::new(SYNC3, 1, 0, 0, self.local_time),
+            };
+            self.send_packet(reply)?;
+        }
+        Ok(())
+    }
+
+    fn too_far_ahead(&self) -> bool {
+        !at_or_after(self.remote_time.wrapping_add(MAX_AHEAD), self.local_time)
+    }
+
+    /// Handles the packets that have arrived, waiting up to `timeout` for
+    /// some if given.
+    fn read_packets(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.fill(timeout)?;
+        while let Some(packet) = self.next_packet() {
+            self.handle(packet)?;
+        }
+        Ok(())
+    }
+}
+
+impl LinkCable for BgbLink {
+    fn send(&mut self, byte: u8, control: u8, time: u64) -> io::Result<()> {
+        self.local_time = timestamp(time);
+        self.reply = None;
+        self.send_packet(Packet::new(SYNC1, byte, control | 0x80, 0, self.local_time))
+    }
+
+    fn receive(&mut self) -> io::Result<u8> {
+        let deadline = Instant::now() + TIMEOUT;
+        while self.reply.is_none() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            self.read_packets(Some(deadline - now))?;
+            // This side is clocking, so it can't also be clocked
+            while self.transfers.pop_front().is_some() {
+                self.send_packet(Packet::new(SYNC3, 1, 0, 0, self.local_time))?;
+            }
+        }
+        match self.reply.take() {
+            Some(byte) => Ok(byte),
+            None => {
+                self.stale_replies += 1;
+                Ok(0xff)
+            }
+        }
+    }
+
+    fn poll(&mut self, serial: &mut SerialLink, interrupts: &mut InterruptController, time: u64) -> io::Result<()> {
+        self.local_time = timestamp(time);
+        self.read_packets(None)?;
+        self.apply_transfers(serial, interrupts)?;
+
+        self.polls = self.polls.wrapping_add(1);
+        if self.polls.is_multiple_of(8) || self.too_far_ahead() {
+            self.send_time()?;
+        }
+
+        // Wait for the other side to catch up, unless it seems to be paused
+        let deadline = Instant::now() + TIMEOUT;
+        while self.too_far_ahead() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            self.read_packets(Some(deadline - now))?;
+            self.apply_transfers(serial, interrupts)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packet_encoding() {
+        let packet = Packet::new(SYNC1, 0x42, 0x81, 0, 0x1234_5678);
+        assert_eq!(packet.to_bytes(), [104, 0x42, 0x81, 0, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(Packet::from_bytes(&packet.to_bytes()), packet);
+    }
+
+    #[test]
+    fn test_timestamp_order() {
+        assert!(at_or_after(10, 5));
+        assert!(at_or_after(5, 5));
+        assert!(!at_or_after(5, 10));
+        assert!(at_or_after(3, TIMESTAMP_MASK - 2));
+    }
+
+    #[test]
+    fn test_timestamp_wrap() {
+        // Past where a 32-bit 8MHz count would wrap
+        let time = (1u64 << 32) - 4;
+        assert_eq!(timestamp(time), 0x3fff_ffff);
+        assert_eq!(timestamp(time + 4), 0x4000_0000);
+        assert!(at_or_after(timestamp(time + 4), timestamp(time)));
+
+        // And where the 31-bit timestamp itself wraps
+        let time = (1u64 << 33) - 4;
+        assert_eq!(timestamp(time), TIMESTAMP_MASK);
+        assert_eq!(timestamp(time + 4), 0);
+        assert!(at_or_after(timestamp(time + 4), timestamp(time)));
+    }
+}
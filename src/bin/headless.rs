@@ -0,0 +1,115 @@
+use gameboy::cli::{self, CliOptions, Command};
+use gameboy::frontend::HeadlessFrontend;
+use gameboy::{screenshot, GameBoy, CYCLES_PER_FRAME};
+use gumdrop::Options;
+use std::io::Write;
+use std::path::Path;
+
+/// Returns true if `frame` matches the reference image.
+fn compare_reference(reference: &Path, frame: &[u8]) -> bool {
+    match screenshot::compare_reference(reference, frame) {
+        Ok(None) => true,
+        Ok(Some(mismatches)) => {
+            eprintln!(
+                "{} pixels differ from {}, see {}",
+                mismatches,
+                reference.display(),
+                reference.with_extension("diff.png").display()
+            );
+            false
+        }
+        Err(e) => {
+            eprintln!("Failed to compare against {}: {}", reference.display(), e);
+            false
+        }
+    }
+}
+
+/// Prints the serial output of both Game Boys since the last call and
+/// appends it to `serial_output`.
+fn print_output(game_boy: &mut GameBoy, partner: Option<&mut GameBoy>, serial_output: &mut Vec<u8>) {
+    let mut output = game_boy.cpu.mmu.serial_link.take_output();
+    if let Some(partner) = partner {
+        output.extend(partner.cpu.mmu.serial_link.take_output());
+    }
+    if !output.is_empty() {
+        let stdout = std::io::stdout();
+        let mut handle = stdout.lock();
+        handle.write_all(&output).expect("Failed to write serial output");
+        handle.flush().expect("Failed to write serial output");
+        serial_output.extend(output);
+    }
+}
+
+fn contains_until(opts: &CliOptions, serial_output: &[u8]) -> bool {
+    opts.until
+        .as_ref()
+        .is_some_and(|until| String::from_utf8_lossy(serial_output).contains(until.as_str()))
+}
+
+fn main() {
+    let opts = CliOptions::parse_args_default_or_exit();
+    if let Some(Command::Disasm(disasm_opts)) = &opts.command {
+        // Only fails when stdout is closed, e.g. piped into head
+        let _ = cli::disasm(&opts.rom, disasm_opts);
+        return;
+    }
+
+    let (mut game_boy, mut partner) = cli::setup(&opts);
+    game_boy.cpu.mmu.serial_link.capture_output();
+    if let Some((partner, _)) = partner.as_mut() {
+        partner.cpu.mmu.serial_link.capture_output();
+    }
+    let mut frontend = HeadlessFrontend::default();
+    let mut serial_output = vec![];
+
+    let total_cycles = opts.frames as u64 * CYCLES_PER_FRAME as u64;
+    let mut found = false;
+    for cycle in 0..total_cycles {
+        match partner.as_mut() {
+            Some((partner, pair)) => {
+                pair.step([&mut game_boy, partner], &mut frontend);
+                if partner.quit_requested() {
+                    break;
+                }
+            }
+            None => game_boy.step(&mut frontend),
+        }
+        if game_boy.quit_requested() || (opts.play.is_some() && game_boy.movie_mode().is_none()) {
+            break;
+        }
+
+        if (cycle + 1) % CYCLES_PER_FRAME as u64 == 0 {
+            print_output(&mut game_boy, partner.as_mut().map(|(partner, _)| partner), &mut serial_output);
+            if contains_until(&opts, &serial_output) {
+                found = true;
+                break;
+            }
+        }
+    }
+    // Catch bytes sent in the last frame, or before quitting mid-frame
+    print_output(&mut game_boy, partner.as_mut().map(|(partner, _)| partner), &mut serial_output);
+    found |= contains_until(&opts, &serial_output);
+
+    game_boy.cpu.mmu.cartridge.save();
+    if let Some((partner, _)) = partner.as_mut() {
+        partner.cpu.mmu.cartridge.save();
+    }
+
+    cli::finish(&opts, &mut game_boy);
+
+    if let Some(path) = &opts.screenshot {
+        if let Err(e) = screenshot::save_frame(path, &frontend.frame) {
+            eprintln!("Failed to save screenshot {}: {}", path, e);
+        }
+    }
+
+    let mut passed = opts.until.is_none() || found;
+    if let Some(path) = &opts.reference {
+        passed &= compare_reference(Path::new(path), &frontend.frame);
+    }
+
+    if !passed {
+        std::process::exit(1);
+    }
+}
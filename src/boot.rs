@@ -0,0 +1,142 @@
+//! Starting a cartridge without a boot ROM, in the state the boot ROM would
+//! have left the machine in.
+
+use super::{Cpu, Mmu};
+
+/// The ® next to the logo, one bitplane.
+const REGISTERED_TILE: [u8; 8] = [0x3c, 0x42, 0xb9, 0xa5, 0xb9, 0xa5, 0x42, 0x3c];
+
+/// Colors the CGB boot ROM gives DMG cartridges it has no palette for, as RGB555.
+const CGB_DMG_BACKGROUND: [u16; 4] = [0x7fff, 0x1bef, 0x6180, 0x0000];
+const CGB_DMG_OBJECTS: [u16; 4] = [0x7fff, 0x421f, 0x1cf2, 0x0000];
+
+/// IO registers after boot, written in order. NR52 comes first to power the
+/// APU; channel 1 is left running as after the boot sound, at an inaudible
+/// frequency.
+const IO_REGISTERS: [(usize, u8); 32] = [
+    (0xff26, 0xf1),
+    (0xff10, 0x80),
+    (0xff11, 0xbf),
+    (0xff12, 0xf3),
+    (0xff13, 0xff),
+    (0xff14, 0xbf),
+    (0xff16, 0x3f),
+    (0xff17, 0x00),
+    (0xff18, 0xff),
+    (0xff19, 0xbf),
+    (0xff1a, 0x7f),
+    (0xff1b, 0xff),
+    (0xff1c, 0x9f),
+    (0xff1d, 0xff),
+    (0xff1e, 0xbf),
+    (0xff20, 0xff),
+    (0xff21, 0x00),
+    (0xff22, 0x00),
+    (0xff23, 0xbf),
+    (0xff24, 0x77),
+    (0xff25, 0xf3),
+    (0xff05, 0x00),
+    (0xff06, 0x00),
+    (0xff07, 0xf8),
+    (0xff0f, 0xe1),
+    (0xff42, 0x00),
+    (0xff43, 0x00),
+    (0xff45, 0x00),
+    (0xff47, 0xfc),
+    (0xff48, 0xff),
+    (0xff49, 0xff),
+    (0xffff, 0x00),
+];
+
+/// Sets registers, IO, palettes and VRAM to the documented post-boot state
+/// of the model being emulated and points PC at the cartridge entry point.
+pub fn skip_boot(cpu: &mut Cpu) {
+    let dmg_model = cpu.mmu.dmg_mode;
+    let cgb_cartridge = cpu.mmu.peek_byte(0x143) & 0x80 != 0;
+    let header_checksum = cpu.mmu.peek_byte(0x14d);
+
+    let registers = &mut cpu.registers;
+    if dmg_model {
+        registers.set_af(if header_checksum == 0 { 0x0180 } else { 0x01b0 });
+        registers.set_bc(0x0013);
+        registers.set_de(0x00d8);
+        registers.set_hl(0x014d);
+    } else if cgb_cartridge {
+        registers.set_af(0x1180);
+        registers.set_bc(0x0000);
+        registers.set_de(0xff56);
+        registers.set_hl(0x000d);
+    } else {
+        registers.set_af(0x1180);
+        registers.set_bc(0x0000);
+        registers.set_de(0x0008);
+        registers.set_hl(0x007c);
+    }
+    cpu.sp = 0xfffe;
+    cpu.pc = 0x100;
+
+    let mmu = &mut cpu.mmu;
+    if dmg_model {
+        // The DMG leaves its logo in VRAM
+        load_logo(mmu);
+        mmu.timer.set_divider(0xab);
+    } else if cgb_cartridge {
+        // Every background palette white
+        mmu.write_byte(0xff68, 0x80);
+        for _ in 0..0x20 {
+            mmu.write_byte(0xff69, 0xff);
+            mmu.write_byte(0xff69, 0x7f);
+        }
+    } else {
+        // Lock the CGB into DMG compatibility, with DMG-style sprite priority
+        mmu.write_byte(0xff4c, 0x04);
+        mmu.write_byte(0xff6c, 0x01);
+        mmu.ppu.set_dmg_colors(CGB_DMG_BACKGROUND, [CGB_DMG_OBJECTS, CGB_DMG_OBJECTS]);
+    }
+    for &(address, value) in IO_REGISTERS.iter() {
+        mmu.write_byte(address, value);
+    }
+    // LCD on last, once VRAM is written
+    mmu.write_byte(0xff40, 0x91);
+}
+
+/// Copies the logo from the cartridge header into tiles 1 to 24 and the ®
+/// into tile 25, each logo pixel doubled, and places them mid-screen.
+fn load_logo(mmu: &mut Mmu) {
+    for i in 0..0x30 {
+        let byte = mmu.peek_byte(0x104 + i);
+        for (half, nibble) in [byte >> 4, byte & 0x0f].iter().enumerate() {
+            let row = double_bits(*nibble);
+            let address = 0x8010 + i * 8 + half * 4;
+            mmu.write_byte(address, row);
+            mmu.write_byte(address + 2, row);
+        }
+    }
+    for (i, &row) in REGISTERED_TILE.iter().enumerate() {
+        mmu.write_byte(0x8190 + i * 2, row);
+    }
+    for i in 0..12 {
+        mmu.write_byte(0x9904 + i, i as u8 + 1);
+        mmu.write_byte(0x9924 + i, i as u8 + 13);
+    }
+    mmu.write_byte(0x9910, 0x19);
+}
+
+/// Widens 4 pixels to 8, e.g. `0b1010` to `0b11001100`.
+fn double_bits(nibble: u8) -> u8 {
+    (0..4)
+        .filter(|bit| nibble & (1 << bit) != 0)
+        .fold(0, |row, bit| row | 0x03 << (bit * 2))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_double_bits() {
+        assert_eq!(double_bits(0b1010), 0b11001100);
+        assert_eq!(double_bits(0b0001), 0b00000011);
+        assert_eq!(double_bits(0x0f), 0xff);
+    }
+}
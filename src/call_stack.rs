@@ -0,0 +1,68 @@
+use super::debugger::rom_bank;
+use super::Cpu;
+
+const MAX_DEPTH: usize = 64;
+const INTERRUPT_VECTORS: [usize; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+/// CALL and RST opcodes, including conditional calls.
+fn is_call(opcode: u8) -> bool {
+    matches!(opcode, 0xc4 | 0xcc | 0xcd | 0xd4 | 0xdc | 0xc7 | 0xcf | 0xd7 | 0xdf | 0xe7 | 0xef | 0xf7 | 0xff)
+}
+
+/// A call or interrupt that hasn't returned yet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// The CALL or RST instruction, or the instruction before an interrupt.
+    pub call_site: usize,
+    pub call_bank: Option<usize>,
+    /// The function or interrupt handler that was entered.
+    pub target: usize,
+    pub target_bank: Option<usize>,
+    /// SP just after the return address was pushed.
+    sp: usize,
+}
+
+/// Shadow call stack, rebuilt from how PC and SP change between instructions.
+#[derive(Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    last_pc: usize,
+    last_sp: usize,
+    last_opcode: u8,
+}
+
+impl CallStack {
+    /// Call at every instruction start.
+    pub fn update(&mut self, cpu: &Cpu) {
+        let pc = cpu.debug_pc();
+        let sp = cpu.sp;
+
+        // Returning, or otherwise popping the return address, ends the frame
+        while self.frames.last().is_some_and(|frame| sp > frame.sp) {
+            self.frames.pop();
+        }
+
+        let pushed = sp == self.last_sp.wrapping_sub(2) & 0xffff;
+        if pushed && (is_call(self.last_opcode) || INTERRUPT_VECTORS.contains(&pc)) {
+            if self.frames.len() == MAX_DEPTH {
+                self.frames.remove(0);
+            }
+            self.frames.push(Frame {
+                call_site: self.last_pc,
+                call_bank: rom_bank(cpu, self.last_pc),
+                target: pc,
+                target_bank: rom_bank(cpu, pc),
+                sp,
+            });
+        }
+
+        self.last_pc = pc;
+        self.last_sp = sp;
+        self.last_opcode = cpu.opcode1;
+    }
+
+    /// Innermost frame last.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+}
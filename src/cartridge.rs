@@ -1,9 +1,16 @@
+use super::cheats::Cheats;
 use super::mbc::*;
+use super::symbols::{symbol_path, Symbols};
+use nanoserde::DeBinErr;
 use std::path::{Path, PathBuf};
 
 pub struct Cartridge {
     mbc: Box<dyn Mbc>,
+    rom_path: PathBuf,
     save_path: PathBuf,
+    rom_crc32: u32,
+    symbols: Symbols,
+    pub cheats: Cheats,
 }
 
 
@@ -15,7 +22,14 @@ impl Cartridge {
         save_extension.push("save");
         save_path.set_extension(save_extension);
 
-        let rom = std::fs::read(rom_path).unwrap();
+        let rom = std::fs::read(&rom_path).unwrap();
+        let rom_crc32 = crc32(&rom);
+        let symbols = Symbols::load(symbol_path(&rom_path)).unwrap_or_default();
+        let cheat_path = cheat_path(rom_path.as_ref());
+        let cheats = Cheats::load(&cheat_path).unwrap_or_else(|e| {
+            eprintln!("Ignoring cheats in {}: {}", cheat_path.display(), e);
+            Cheats::default()
+        });
         let mut battery = false;
         let mut rtc = false;
         let mut load_data = vec![];
@@ -62,7 +76,11 @@ impl Cartridge {
         
         Cartridge {
             mbc,
+            rom_path: rom_path.as_ref().to_path_buf(),
             save_path,
+            rom_crc32,
+            symbols,
+            cheats,
         }
     }
 
@@ -71,16 +89,88 @@ impl Cartridge {
     }
 
     pub fn read(&self, address: usize) -> u8 {
-        self.mbc.read(address)
+        let value = self.mbc.read(address);
+        if address < 0x8000 {
+            self.cheats.patch_rom(address, value)
+        } else {
+            value
+        }
     }
 
     pub fn write(&mut self, address: usize, value: u8) {
         self.mbc.write(address, value);
     }
 
+    /// ROM bank currently mapped at `address` (below 0x8000).
+    pub fn rom_bank(&self, address: usize) -> usize {
+        self.mbc.rom_bank(address)
+    }
+
+    /// All cartridge RAM banks, for searching.
+    pub fn ram(&self) -> &[u8] {
+        self.mbc.ram()
+    }
+
     pub fn update_rtc(&mut self, millis: u64) {
         self.mbc.update_rtc(millis);
     }
+
+    /// Catches the RTC up with the wall clock, given as microseconds since
+    /// the Unix epoch. Emulation itself never reads the wall clock.
+    pub fn sync_rtc(&mut self, unix_micros: u64) {
+        self.mbc.sync_rtc(unix_micros);
+    }
+
+    /// Path of numbered save state `slot`, next to the ROM.
+    pub fn state_path(&self, slot: u8) -> PathBuf {
+        let mut state_path = self.rom_path.clone();
+        let mut state_extension = state_path.extension().unwrap().to_owned();
+        state_extension.push(format!("state{}", slot));
+        state_path.set_extension(state_extension);
+        state_path
+    }
+
+    /// Path of the cheat file, next to the ROM.
+    pub fn cheat_path(&self) -> PathBuf {
+        cheat_path(&self.rom_path)
+    }
+
+    /// CRC-32 of the whole ROM file, used to tell ROMs apart.
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+
+    /// Labels from the `.sym` file next to the ROM, if there is one.
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn save_state(&self, output: &mut Vec<u8>) {
+        self.mbc.save_state(output);
+    }
+
+    pub fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.mbc.load_state(offset, data)
+    }
+}
+
+fn cheat_path(rom_path: &Path) -> PathBuf {
+    let mut cheat_path = rom_path.to_path_buf();
+    let mut cheat_extension = cheat_path.extension().unwrap().to_owned();
+    cheat_extension.push("cheats");
+    cheat_path.set_extension(cheat_extension);
+    cheat_path
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for byte in data.iter() {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
 }
 
 fn load_save_file<P: AsRef<Path>>(save_path: P, size: usize) -> Vec<u8> {
@@ -93,3 +183,14 @@ fn load_save_file<P: AsRef<Path>>(save_path: P, size: usize) -> Vec<u8> {
     load_data
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// A decoded cheat code.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CheatCode {
+    /// `ABC-DEF[-GHI]`: replaces a ROM byte, optionally only while the
+    /// original byte equals `compare`.
+    GameGenie { address: usize, value: u8, compare: Option<u8> },
+    /// `ttvvaaaa`: writes `value` to RAM at `address` every frame. Type `01`
+    /// writes through the current bank mapping, `9x` writes to WRAM bank `x`.
+    GameShark { bank: Option<u8>, address: usize, value: u8 },
+}
+
+fn hex_digit(c: char) -> Option<usize> {
+    c.to_digit(16).map(|d| d as usize)
+}
+
+impl CheatCode {
+    pub fn parse(text: &str) -> Option<Self> {
+        let digits: Option<Vec<usize>> = text.chars().filter(|&c| c != '-').map(hex_digit).collect();
+        let d = digits?;
+        let dashes: Vec<usize> = text.match_indices('-').map(|(i, _)| i).collect();
+
+        match (d.len(), dashes.as_slice()) {
+            (6, [3]) | (9, [3, 7]) => {
+                let value = (d[0] << 4 | d[1]) as u8;
+                let address = (d[5] ^ 0xf) << 12 | d[2] << 8 | d[3] << 4 | d[4];
+                if address >= 0x8000 {
+                    return None;
+                }
+                let compare = if d.len() == 9 {
+                    let encoded = (d[6] << 4 | d[8]) as u8;
+                    Some(encoded.rotate_right(2) ^ 0xba)
+                } else {
+                    None
+                };
+                Some(CheatCode::GameGenie { address, value, compare })
+            }
+            (8, []) => {
+                let code_type = (d[0] << 4 | d[1]) as u8;
+                let value = (d[2] << 4 | d[3]) as u8;
+                let address = d[6] << 12 | d[7] << 8 | d[4] << 4 | d[5];
+                let bank = match code_type {
+                    0x01 => None,
+                    0x90..=0x97 => Some(code_type & 0x07),
+                    _ => return None,
+                };
+                Some(CheatCode::GameShark { bank, address, value })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CheatCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CheatCode::GameGenie { address, value, compare: Some(compare) } => {
+                write!(f, "Game Genie: [{:04x}] = {:02x} if {:02x}", address, value, compare)
+            }
+            CheatCode::GameGenie { address, value, compare: None } => {
+                write!(f, "Game Genie: [{:04x}] = {:02x}", address, value)
+            }
+            CheatCode::GameShark { bank: Some(bank), address, value } => {
+                write!(f, "GameShark: [{:04x}] (WRAM bank {}) = {:02x}", address, bank, value)
+            }
+            CheatCode::GameShark { bank: None, address, value } => {
+                write!(f, "GameShark: [{:04x}] = {:02x}", address, value)
+            }
+        }
+    }
+}
+
+pub struct Cheat {
+    /// The code as entered, kept for saving.
+    pub text: String,
+    pub code: CheatCode,
+    pub description: String,
+    pub enabled: bool,
+}
+
+/// Cheats for one ROM. The file has one `[-]CODE [description]` per line,
+/// where a leading `-` disables the cheat and `;` starts a comment.
+pub struct Cheats {
+    list: Vec<Cheat>,
+    /// Turns every cheat off without forgetting which ones are enabled.
+    pub active: bool,
+}
+
+impl Default for Cheats {
+    fn default() -> Self {
+        Cheats { list: vec![], active: true }
+    }
+}
+
+impl Cheats {
+    /// Parses a cheat file, reporting lines that aren't valid codes.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Cheats::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('-') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if !cheats.add(code, description.trim()) {
+                return Err(format!("line {}: invalid cheat code {}", number + 1, code));
+            }
+            cheats.list.last_mut().unwrap().enabled = enabled;
+        }
+        Ok(cheats)
+    }
+
+    /// Loads `path`, or returns no cheats if it doesn't exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Cheats::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Cheats::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut text = String::new();
+        for cheat in self.list.iter() {
+            let prefix = if cheat.enabled { "" } else { "-" };
+            text += &format!("{}{} {}\n", prefix, cheat.text, cheat.description);
+        }
+        std::fs::write(path, text)
+    }
+
+    /// Adds an enabled cheat. Returns false if `code` isn't valid.
+    pub fn add(&mut self, code: &str, description: &str) -> bool {
+        match CheatCode::parse(code) {
+            Some(parsed) => {
+                self.list.push(Cheat {
+                    text: code.to_uppercase(),
+                    code: parsed,
+                    description: description.to_string(),
+                    enabled: true,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index < self.list.len() {
+            self.list.remove(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns false if there is no cheat `index`.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        match self.list.get_mut(index) {
+            Some(cheat) => {
+                cheat.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Enabled cheats, all enabled, e.g. to replay a movie with the cheats
+    /// it was recorded with. Returns `None` if a code isn't valid.
+    pub fn from_codes(codes: &[String]) -> Option<Self> {
+        let mut cheats = Cheats::default();
+        for code in codes.iter() {
+            if !cheats.add(code, "") {
+                return None;
+            }
+        }
+        Some(cheats)
+    }
+
+    /// The codes currently in effect.
+    pub fn enabled_codes(&self) -> Vec<String> {
+        let active = self.active;
+        self.list.iter().filter(|c| active && c.enabled).map(|c| c.text.clone()).collect()
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.list
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = CheatCode> + '_ {
+        let active = self.active;
+        self.list.iter().filter(move |c| active && c.enabled).map(|c| c.code)
+    }
+
+    /// Applies Game Genie codes to a byte read from ROM.
+    pub fn patch_rom(&self, address: usize, value: u8) -> u8 {
+        for code in self.enabled() {
+            if let CheatCode::GameGenie { address: patch_address, value: patched, compare } = code {
+                if patch_address == address && compare.is_none_or(|compare| compare == value) {
+                    return patched;
+                }
+            }
+        }
+        value
+    }
+
+    /// GameShark writes to make this frame, as `(wram bank, address, value)`.
+    pub fn ram_writes(&self) -> Vec<(Option<u8>, usize, u8)> {
+        self.enabled()
+            .filter_map(|code| match code {
+                CheatCode::GameShark { bank, address, value } => Some((bank, address, value)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_codes() {
+        assert_eq!(
+            CheatCode::parse("00A-17B-C49"),
+            Some(CheatCode::GameGenie { address: 0x4a17, value: 0x00, compare: Some(0xc9u8.rotate_right(2) ^ 0xba) })
+        );
+        assert_eq!(
+            CheatCode::parse("3e0-59f"),
+            Some(CheatCode::GameGenie { address: 0x0059, value: 0x3e, compare: None })
+        );
+        assert_eq!(
+            CheatCode::parse("010363DA"),
+            Some(CheatCode::GameShark { bank: None, address: 0xda63, value: 0x03 })
+        );
+        assert_eq!(
+            CheatCode::parse("9163A2D0"),
+            Some(CheatCode::GameShark { bank: Some(1), address: 0xd0a2, value: 0x63 })
+        );
+        assert_eq!(CheatCode::parse("00A17BC49"), None);
+        assert_eq!(CheatCode::parse("020363DA"), None);
+    }
+
+    #[test]
+    fn test_cheat_file() {
+        let cheats = Cheats::parse("; lives\n010363DA Infinite lives\n-3E0-59F Skip intro\n").unwrap();
+        assert_eq!(cheats.list().len(), 2);
+        assert_eq!(cheats.list()[0].description, "Infinite lives");
+        assert!(!cheats.list()[1].enabled);
+        assert_eq!(cheats.patch_rom(0x59, 0x12), 0x12);
+        assert_eq!(cheats.ram_writes(), vec![(None, 0xda63, 0x03)]);
+        assert!(Cheats::parse("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_enabled_codes() {
+        let mut cheats = Cheats::parse("010363DA\n-3E0-59F\n").unwrap();
+        let codes = cheats.enabled_codes();
+        assert_eq!(codes, vec!["010363DA".to_string()]);
+        assert_eq!(Cheats::from_codes(&codes).unwrap().enabled_codes(), codes);
+        cheats.active = false;
+        assert!(cheats.enabled_codes().is_empty());
+        assert!(Cheats::from_codes(&["nonsense".to_string()]).is_none());
+    }
+}
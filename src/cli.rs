@@ -0,0 +1,279 @@
+//! Command line options and setup shared by the windowed and headless
+//! binaries.
+
+use super::bgb_link::BgbLink;
+use super::debugger::Breakpoint;
+use super::link::TcpLink;
+use super::linked::LinkedPair;
+use super::mmu::BOOT_ROM_SIZE;
+use super::movie::{Movie, MovieStart};
+use super::printer::Printer;
+use super::serial::LinkCable;
+use super::symbols::{self, Symbols};
+use super::watchpoints::Watchpoint;
+use super::{disassembler, GameBoy};
+use gumdrop::Options;
+use std::io::Write;
+use std::time::SystemTime;
+
+#[derive(Options)]
+pub struct CliOptions {
+    #[options(required, help = "path to ROM")]
+    pub rom: String,
+
+    #[options(help = "print help message")]
+    pub help: bool,
+
+    #[options(help = "run in non-color gameboy mode")]
+    pub dmg_mode: bool,
+
+    #[options(no_short, help = "run this boot ROM first instead of starting the cartridge directly")]
+    pub boot_rom: Option<String>,
+
+    #[options(no_short, help = "record input to a movie file")]
+    pub record: Option<String>,
+
+    #[options(help = "play back a movie file")]
+    pub play: Option<String>,
+
+    #[options(no_short, help = "load this save state slot before starting, e.g. to record from it")]
+    pub start_slot: Option<u8>,
+
+    #[options(help = "open the debugger before the first instruction")]
+    pub debug: bool,
+
+    #[options(help = "break at a PC, as a label, addr or bank:addr in hex (repeatable)")]
+    pub breakpoint: Vec<String>,
+
+    #[options(help = "watch memory, as [r|w|c:]addr[-end] in hex (repeatable)")]
+    pub watch: Vec<String>,
+
+    #[options(help = "wait for a GDB remote protocol client on this local port")]
+    pub gdb: Option<u16>,
+
+    #[options(no_short, help = "wait for another emulator to connect a link cable on this local port")]
+    pub link_listen: Option<u16>,
+
+    #[options(no_short, help = "connect a link cable to another emulator, as port or host:port")]
+    pub link_connect: Option<String>,
+
+    #[options(no_short, help = "use the BGB 1.4 protocol for --link-listen and --link-connect")]
+    pub link_bgb: bool,
+
+    #[options(no_short, help = "run a second Game Boy with this ROM, linked by cable and shown on the right")]
+    pub link_rom: Option<String>,
+
+    #[options(no_short, help = "connect a Game Boy Printer, saving printouts next to the ROM")]
+    pub printer: bool,
+
+    #[options(help = "log every instruction to a file in Gameboy Doctor format")]
+    pub trace: Option<String>,
+
+    #[options(no_short, help = "append ; Label+offset to --trace lines, which Gameboy Doctor won't accept")]
+    pub trace_symbols: bool,
+
+    #[options(help = "enable a Game Genie or GameShark code for this run (repeatable)")]
+    pub cheat: Vec<String>,
+
+    #[options(no_short, help = "write cycles per function and per address to a file on exit")]
+    pub profile: Option<String>,
+
+    #[options(no_short, help = "write cycles per call stack to a file on exit, in flamegraph folded format")]
+    pub profile_folded: Option<String>,
+
+    #[options(no_short, help = "windowed: seconds of gameplay kept for rewinding, 0 to disable (off with --link-rom)", default = "60")]
+    pub rewind_seconds: u32,
+
+    #[options(help = "headless: number of frames to run for", default = "3600")]
+    pub frames: u32,
+
+    #[options(help = "headless: stop early once the serial output contains this text")]
+    pub until: Option<String>,
+
+    #[options(short = "s", help = "headless: save the last frame as a PNG")]
+    pub screenshot: Option<String>,
+
+    #[options(short = "R", help = "headless: compare the last frame against a PNG, writing a .diff.png on mismatch")]
+    pub reference: Option<String>,
+
+    #[options(command)]
+    pub command: Option<Command>,
+}
+
+#[derive(Options)]
+pub enum Command {
+    #[options(help = "disassemble ROM banks instead of running")]
+    Disasm(DisasmOptions),
+}
+
+#[derive(Options)]
+pub struct DisasmOptions {
+    #[options(help = "print help message")]
+    pub help: bool,
+
+    #[options(help = "first bank to disassemble", default = "0")]
+    pub bank: usize,
+
+    #[options(help = "last bank to disassemble (default: same as --bank)")]
+    pub end_bank: Option<usize>,
+}
+
+/// Prints the disassembly of the requested ROM banks to stdout.
+pub fn disasm(rom_path: &str, opts: &DisasmOptions) -> std::io::Result<()> {
+    let rom = std::fs::read(rom_path).unwrap_or_else(|e| panic!("Failed to read {}: {}", rom_path, e));
+    let bank_count = rom.len().div_ceil(0x4000);
+    let end_bank = opts.end_bank.unwrap_or(opts.bank);
+    let error = if bank_count == 0 {
+        Some(format!("{} is empty", rom_path))
+    } else if opts.bank >= bank_count || end_bank >= bank_count {
+        Some(format!("{} only has banks 0 to {}", rom_path, bank_count - 1))
+    } else if end_bank < opts.bank {
+        Some(format!("--end-bank {} is before --bank {}", end_bank, opts.bank))
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    let symbols = Symbols::load(symbols::symbol_path(rom_path)).unwrap_or_default();
+
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+    for bank in opts.bank..=end_bank {
+        writeln!(handle, "; bank {:02x}", bank)?;
+        for line in disassembler::disassemble_bank(&rom, bank, &symbols) {
+            writeln!(handle, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates the Game Boy described by the options, with everything attached,
+/// plus the second Game Boy for `--link-rom`. A movie to play is started
+/// last, so that it replaces any `--cheat` codes with its own.
+pub fn setup(opts: &CliOptions) -> (GameBoy, Option<(GameBoy, LinkedPair)>) {
+    let movie = opts.play.as_ref().map(|path| {
+        Movie::load(path).unwrap_or_else(|e| panic!("Failed to load movie {}: {}", path, e))
+    });
+    let dmg_mode = movie.as_ref().map_or(opts.dmg_mode, |movie| movie.dmg_mode);
+
+    let boot_rom = opts.boot_rom.as_ref().map(|path| {
+        let data = std::fs::read(path).unwrap_or_else(|e| panic!("Failed to read boot ROM {}: {}", path, e));
+        if data.len() > BOOT_ROM_SIZE {
+            eprintln!("Boot ROM {} is {} bytes, larger than {} bytes", path, data.len(), BOOT_ROM_SIZE);
+            std::process::exit(1);
+        }
+        data
+    });
+    let mut game_boy = GameBoy::new(&opts.rom, dmg_mode, boot_rom.clone());
+    // A movie's RTC runs on emulated time from its start state only
+    let sync_rtc = movie.is_none();
+    if sync_rtc {
+        game_boy.cpu.mmu.cartridge.sync_rtc(unix_micros());
+    }
+    for breakpoint in opts.breakpoint.iter() {
+        let symbols = game_boy.cpu.mmu.cartridge.symbols();
+        let breakpoint = Breakpoint::parse_with_symbols(breakpoint, symbols)
+            .unwrap_or_else(|| panic!("Invalid breakpoint: {}", breakpoint));
+        game_boy.debugger.add_breakpoint(breakpoint);
+    }
+    if opts.debug {
+        game_boy.debugger.request_break();
+    }
+    for code in opts.cheat.iter() {
+        if !game_boy.cpu.mmu.cartridge.cheats.add(code, "") {
+            panic!("Invalid cheat code: {}", code);
+        }
+    }
+    for watchpoint in opts.watch.iter() {
+        let watchpoint = Watchpoint::parse(watchpoint).unwrap_or_else(|| panic!("Invalid watchpoint: {}", watchpoint));
+        game_boy.cpu.mmu.watchpoints.add(watchpoint);
+    }
+    if let Some(port) = opts.gdb {
+        game_boy.attach_gdb(port).unwrap_or_else(|e| panic!("Failed to listen for GDB on port {}: {}", port, e));
+    }
+
+    let links = [opts.link_rom.is_some(), opts.link_listen.is_some(), opts.link_connect.is_some(), opts.printer];
+    if links.iter().filter(|&&link| link).count() > 1 {
+        panic!("Only one of --link-rom, --link-listen, --link-connect and --printer can be used");
+    }
+    if opts.printer {
+        game_boy.connect_link(Box::new(Printer::new(&opts.rom)));
+    }
+    if let Some(port) = opts.link_listen {
+        let link: std::io::Result<Box<dyn LinkCable>> = if opts.link_bgb {
+            BgbLink::listen(port).map(|link| Box::new(link) as _)
+        } else {
+            TcpLink::listen(port).map(|link| Box::new(link) as _)
+        };
+        let link = link.unwrap_or_else(|e| panic!("Failed to listen for link cable on port {}: {}", port, e));
+        game_boy.connect_link(link);
+    }
+    if let Some(address) = &opts.link_connect {
+        let address = match address.parse::<u16>() {
+            Ok(port) => format!("127.0.0.1:{}", port),
+            Err(_) => address.clone(),
+        };
+        let link: std::io::Result<Box<dyn LinkCable>> = if opts.link_bgb {
+            BgbLink::connect(address.as_str()).map(|link| Box::new(link) as _)
+        } else {
+            TcpLink::connect(address.as_str()).map(|link| Box::new(link) as _)
+        };
+        let link = link.unwrap_or_else(|e| panic!("Failed to connect link cable to {}: {}", address, e));
+        game_boy.connect_link(link);
+    }
+
+    if let Some(path) = &opts.trace {
+        game_boy.trace_to(path, opts.trace_symbols).unwrap_or_else(|e| panic!("Failed to create trace {}: {}", path, e));
+    }
+    if opts.profile.is_some() || opts.profile_folded.is_some() {
+        game_boy.enable_profiler();
+    }
+    if let Some(slot) = opts.start_slot {
+        game_boy
+            .load_state_from_slot(slot)
+            .unwrap_or_else(|e| panic!("Failed to load state from slot {}: {}", slot, e));
+    }
+    if let Some(movie) = movie {
+        game_boy.play_movie(movie).expect("Failed to play movie");
+    } else if opts.record.is_some() {
+        let start = if opts.start_slot.is_some() { MovieStart::SaveState } else { MovieStart::PowerOn };
+        game_boy.record_movie(start);
+    }
+
+    let partner = opts.link_rom.as_ref().map(|path| {
+        let mut partner = GameBoy::new(path, dmg_mode, boot_rom.clone());
+        if sync_rtc {
+            partner.cpu.mmu.cartridge.sync_rtc(unix_micros());
+        }
+        let pair = LinkedPair::connect(&mut game_boy, &mut partner);
+        (partner, pair)
+    });
+    (game_boy, partner)
+}
+
+fn unix_micros() -> u64 {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+    now.map_or(0, |duration| duration.as_micros() as u64)
+}
+
+/// Writes the profile and the recorded movie once the run is over.
+pub fn finish(opts: &CliOptions, game_boy: &mut GameBoy) {
+    if let Some(profiler) = game_boy.profiler() {
+        let symbols = game_boy.cpu.mmu.cartridge.symbols();
+        if let Some(path) = &opts.profile {
+            profiler.save_report(path, symbols).unwrap_or_else(|e| panic!("Failed to write profile {}: {}", path, e));
+        }
+        if let Some(path) = &opts.profile_folded {
+            profiler.save_folded(path, symbols).unwrap_or_else(|e| panic!("Failed to write profile {}: {}", path, e));
+        }
+    }
+
+    if let Some(path) = &opts.record {
+        if let Some(movie) = game_boy.stop_movie() {
+            movie.save(path).unwrap_or_else(|e| panic!("Failed to save movie {}: {}", path, e));
+            println!("Saved movie to {}", path);
+        }
+    }
+}
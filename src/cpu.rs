@@ -1,46 +1,113 @@
-use sdl2::render::TextureCreator;
-use sdl2::video::WindowContext;
+use super::boot;
 use super::registers::Registers;
-use super::{Display, InterruptState, DEBUG, Mmu};
+use super::{InterruptState, Mmu};
+use nanoserde::{DeBin, DeBinErr, SerBin};
 use std::path::Path;
 
-pub struct Cpu<'a> {
-    pub mmu: Mmu<'a>,
+pub struct Cpu {
+    pub mmu: Mmu,
     pub pc: usize,
     debug_pc: usize,
     pub sp: usize,
     pub registers: Registers,
     pub cycles: u8,
     pub halt: bool,
+    /// HALT ran with IME off and an interrupt pending: the next opcode is
+    /// fetched without incrementing PC.
+    pub(crate) halt_bug: bool,
+    /// In STOP mode: the CPU, LCD and timers are frozen until a button press.
+    pub stopped: bool,
+    /// Locked up by an illegal opcode. Only a reset recovers.
+    pub locked: bool,
     pub mem_read: Vec<u8>,
     pub opcode1: u8,
     pub opcode2: u8,
     pending_interrupt: bool,
     interrupt_dest: usize,
+    instruction_start: bool,
 }
 
-impl<'a> Cpu<'a> {
-    pub fn new<P: AsRef<Path>>(
-        rom_path: P,
-        sdl: &sdl2::Sdl,
-        display: Display,
-        texture_creator: &'a TextureCreator<WindowContext>,
-        dmg_mode: bool,
-    ) -> Self {
-        Cpu {
-            mmu: Mmu::new(rom_path, sdl, display, texture_creator, dmg_mode),
+impl Cpu {
+    /// Starts in the boot ROM if one is given, otherwise at the cartridge
+    /// entry point in the state the boot ROM would have left.
+    pub fn new<P: AsRef<Path>>(rom_path: P, dmg_mode: bool, boot_rom: Option<Vec<u8>>) -> Self {
+        let skip_boot = boot_rom.is_none();
+        let mut cpu = Cpu {
+            mmu: Mmu::new(rom_path, dmg_mode, boot_rom),
             pc: 0,
             debug_pc: 0,
             sp: 0,
             registers: Registers::new(),
             cycles: 1,
             halt: false,
+            halt_bug: false,
+            stopped: false,
+            locked: false,
             mem_read: vec![],
             opcode1: 0,
             opcode2: 0,
             pending_interrupt: false,
             interrupt_dest: 0,
+            instruction_start: false,
+        };
+        if skip_boot {
+            boot::skip_boot(&mut cpu);
         }
+        cpu
+    }
+
+    pub fn save_state(&self, output: &mut Vec<u8>) {
+        self.pc.ser_bin(output);
+        self.debug_pc.ser_bin(output);
+        self.sp.ser_bin(output);
+        self.registers.ser_bin(output);
+        self.cycles.ser_bin(output);
+        self.halt.ser_bin(output);
+        self.halt_bug.ser_bin(output);
+        self.stopped.ser_bin(output);
+        self.locked.ser_bin(output);
+        self.mem_read.ser_bin(output);
+        self.opcode1.ser_bin(output);
+        self.opcode2.ser_bin(output);
+        self.pending_interrupt.ser_bin(output);
+        self.interrupt_dest.ser_bin(output);
+        self.mmu.save_state(output);
+    }
+
+    pub fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.pc = DeBin::de_bin(offset, data)?;
+        self.debug_pc = DeBin::de_bin(offset, data)?;
+        self.sp = DeBin::de_bin(offset, data)?;
+        self.registers = DeBin::de_bin(offset, data)?;
+        self.cycles = DeBin::de_bin(offset, data)?;
+        self.halt = DeBin::de_bin(offset, data)?;
+        self.halt_bug = DeBin::de_bin(offset, data)?;
+        self.stopped = DeBin::de_bin(offset, data)?;
+        self.locked = DeBin::de_bin(offset, data)?;
+        self.mem_read = DeBin::de_bin(offset, data)?;
+        self.opcode1 = DeBin::de_bin(offset, data)?;
+        self.opcode2 = DeBin::de_bin(offset, data)?;
+        self.pending_interrupt = DeBin::de_bin(offset, data)?;
+        self.interrupt_dest = DeBin::de_bin(offset, data)?;
+        self.mmu.load_state(offset, data)
+    }
+
+    /// Address of the instruction currently executing.
+    pub fn debug_pc(&self) -> usize {
+        self.debug_pc
+    }
+
+    /// Whether the last cycle fetched a new instruction, i.e. the CPU sits
+    /// right before executing the instruction at `debug_pc`.
+    pub fn at_instruction_start(&self) -> bool {
+        self.instruction_start
+    }
+
+    /// Continues execution at `address`. Only valid at an instruction start.
+    pub fn set_pc(&mut self, address: usize) {
+        self.pc = address;
+        self.debug_pc = address;
+        self.opcode1 = self.next_byte();
     }
 
     pub fn next_byte(&mut self) -> u8 {
@@ -82,7 +149,7 @@ impl<'a> Cpu<'a> {
     }
 
     pub fn push_stack(&mut self, value: u8) {
-        self.sp = (self.sp - 1) & 0xffff;
+        self.sp = self.sp.wrapping_sub(1) & 0xffff;
         self.mmu.write_byte(self.sp, value);
     }
 
@@ -93,13 +160,23 @@ impl<'a> Cpu<'a> {
     }
 
     fn process_interrupts(&mut self) {
+        // IME turns on once the instruction after EI has finished
+        let interrupt_controller = &mut self.mmu.interrupt_controller;
+        if interrupt_controller.state == InterruptState::Scheduled && !self.pending_interrupt && self.opcode1 != 0xfb {
+            interrupt_controller.state = InterruptState::Enabled;
+        }
+
         if self.halt && self.mmu.interrupt_controller.state != InterruptState::Enabled {
             self.halt = !self.mmu.interrupt_controller.poll_interrupts();
             self.pending_interrupt = false;
-        } else if let Some(dest) = self.mmu.interrupt_controller.pending_interrupts() {
+        } else if self.mmu.interrupt_controller.start_dispatch() {
             self.pending_interrupt = true;
             self.halt = false;
-            self.interrupt_dest = dest;
+            // After EI; HALT the handler returns to the HALT
+            if self.halt_bug {
+                self.pc -= 1;
+                self.halt_bug = false;
+            }
         }
 
         if self.pending_interrupt {
@@ -109,6 +186,9 @@ impl<'a> Cpu<'a> {
                 3 => {
                     let pc_h = ((self.pc & 0xff00) >> 8) as u8;
                     self.push_stack(pc_h);
+                    // The vector is picked after this push, which may have
+                    // written IE and cancelled the interrupt (Mooneye ie_push)
+                    self.interrupt_dest = self.mmu.interrupt_controller.take_interrupt();
                 }
                 2 => {
                     let pc_l = self.pc as u8;
@@ -122,6 +202,16 @@ impl<'a> Cpu<'a> {
     }
 
     pub fn execute_cycle(&mut self) {
+        self.instruction_start = false;
+        if self.locked {
+            return;
+        }
+        if self.stopped {
+            if !self.mmu.joypad.any_pressed() {
+                return;
+            }
+            self.stopped = false;
+        }
         if self.mmu.dma_config.cpu_halted(self.mmu.ppu.lcd_status.mode) {
             return;
         }
@@ -229,22 +319,14 @@ impl<'a> Cpu<'a> {
                         0xC0..=0xFF => self.set(),
                     }
                 }
-                _ => panic!("Invalid opcode {:02x}", self.opcode1),
+                // 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD
+                _ => {
+                    self.locked = true;
+                    return;
+                }
             }
         }
 
-        if DEBUG && !self.halt && self.cycles == 1 && !self.pending_interrupt {
-            let debug_opcode = if self.opcode1 == 0xcb {
-                (self.opcode1 as u16) << 8 | self.opcode2 as u16
-            } else {
-                self.opcode1 as u16
-            };
-            println!(
-                "PC: {:04x}, Op: {:02x} SP: {:04x} {:2x}",
-                self.debug_pc, debug_opcode, self.sp, self.registers
-            );
-        }
-
         self.cycles = self.cycles.saturating_sub(1);
 
         // Pre-fetch next instruction, or handle pending interrupt
@@ -262,7 +344,13 @@ impl<'a> Cpu<'a> {
 
         if self.cycles == 0 {
             self.debug_pc = self.pc;
-            self.opcode1 = self.next_byte();
+            if self.halt_bug {
+                self.opcode1 = self.mmu.read_byte(self.pc);
+                self.halt_bug = false;
+            } else {
+                self.opcode1 = self.next_byte();
+            }
+            self.instruction_start = true;
         }
     }
 }
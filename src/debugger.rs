@@ -0,0 +1,571 @@
+use super::call_stack::CallStack;
+use super::disassembler::disassemble_with_labels;
+use super::ram_search::{Comparison, RamSearch, Snapshot};
+use super::symbols::Symbols;
+use super::watchpoints::{WatchHit, Watchpoint};
+use super::{Cpu, InterruptState};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// Search results longer than this aren't listed.
+const SEARCH_LIST_LIMIT: usize = 20;
+
+const HELP: &str = "\
+Addresses and values are hex.
+  c, continue            resume execution
+  s, step [n]            execute n instructions (default 1)
+  n, next                step over calls
+  f, finish              run until the current function returns
+  b, break [bank:]addr|label
+                         set a breakpoint
+  d, delete n            delete breakpoint n
+  w, watch [r|w|c:]addr[-end]
+                         watch reads, writes (default) or value changes
+  dw n                   delete watchpoint n
+  l, list                list breakpoints and watchpoints
+  bt, backtrace          show the call stack
+  cheats                 list cheats
+  cheat add code [desc]  add a Game Genie or GameShark code
+  cheat on|off|del n     enable, disable or delete cheat n
+  search start           snapshot WRAM, HRAM and cartridge RAM for a search
+  search equal [value]|changed|increased|decreased
+                         keep addresses that compare so with the last snapshot
+  search list            show the remaining addresses
+  search freeze n [value]
+                         hold address n at value (default: current) with a cheat
+  search code n [value]  show a GameShark code for address n
+  r, regs                show registers and flags
+  set reg value          set a, b, c, d, e, f, h, l, af, bc, de, hl, sp or pc
+  flag z|n|h|c 0|1       set a flag
+  x addr [count]         show count bytes of memory (default 16)
+  q, quit                quit the emulator";
+
+/// A PC breakpoint, optionally limited to one ROM bank.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub bank: Option<usize>,
+    pub address: usize,
+}
+
+impl Breakpoint {
+    /// Parses `addr` or `bank:addr`, both hex.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.split_once(':') {
+            Some((bank, address)) => Some(Breakpoint {
+                bank: Some(parse_hex(bank)?),
+                address: parse_hex(address)?,
+            }),
+            None => Some(Breakpoint { bank: None, address: parse_hex(text)? }),
+        }
+    }
+
+    /// Parses a label from `symbols`, or `addr` or `bank:addr` as `parse` does.
+    /// Labels outside switchable ROM match in any bank.
+    pub fn parse_with_symbols(text: &str, symbols: &Symbols) -> Option<Self> {
+        match symbols.address_of(text) {
+            Some((bank, address)) => Some(Breakpoint {
+                bank: if (0x4000..0x8000).contains(&address) { Some(bank) } else { None },
+                address,
+            }),
+            None => Breakpoint::parse(text),
+        }
+    }
+
+    fn matches(&self, cpu: &Cpu) -> bool {
+        let pc = cpu.debug_pc();
+        pc == self.address && (self.bank.is_none() || self.bank == rom_bank(cpu, pc))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02x}:{:04x}", bank, self.address),
+            None => write!(f, "{:04x}", self.address),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Step {
+    None,
+    Into(u32),
+    /// Until the call stack is back to `depth` frames, so calls, RSTs and
+    /// interrupts run through wherever they return to.
+    Over { depth: usize },
+    /// Until the innermost of `depth` frames has returned.
+    Out { depth: usize },
+}
+
+enum Prompt {
+    Stay,
+    Resume,
+    Quit,
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    step: Step,
+    break_requested: bool,
+    call_stack: CallStack,
+    ram_search: Option<RamSearch>,
+    /// Whether the prompt has been opened this session.
+    used: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: vec![],
+            step: Step::None,
+            break_requested: false,
+            call_stack: CallStack::default(),
+            ram_search: None,
+            used: false,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Breaks before the next instruction.
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    /// Called at every instruction start. Returns whether to open the prompt.
+    pub fn should_break(&mut self, cpu: &Cpu) -> bool {
+        self.call_stack.update(cpu);
+
+        if self.break_requested {
+            self.break_requested = false;
+            return true;
+        }
+
+        let step_done = match self.step {
+            Step::None => false,
+            Step::Into(count) => {
+                self.step = Step::Into(count - 1);
+                count == 1
+            }
+            Step::Over { depth } => self.call_stack.frames().len() <= depth,
+            Step::Out { depth } => self.call_stack.frames().len() < depth,
+        };
+        if step_done {
+            self.step = Step::None;
+            return true;
+        }
+
+        if let Some(index) = self.breakpoints.iter().position(|b| b.matches(cpu)) {
+            println!("Breakpoint {} hit at {}", index, format_pc(cpu, cpu.debug_pc()));
+            self.step = Step::None;
+            return true;
+        }
+        false
+    }
+
+    /// Reports a watchpoint hit during the instruction at `debug_pc` (or a DMA
+    /// running alongside it) and opens the prompt. Returns false if the user
+    /// asked to quit.
+    pub fn watchpoint_hit(&mut self, cpu: &mut Cpu, hit: WatchHit, dma: bool) -> bool {
+        let source = if dma { " by DMA" } else { "" };
+        println!("{}{} at PC {}", hit, source, format_pc(cpu, cpu.debug_pc()));
+        self.step = Step::None;
+        self.prompt(cpu)
+    }
+
+    /// Opens the prompt after an illegal opcode locked up the CPU, if the
+    /// debugger is in use. Returns false if the user asked to quit.
+    pub fn locked_up(&mut self, cpu: &mut Cpu) -> bool {
+        if self.breakpoints.is_empty() && !self.used {
+            return true;
+        }
+        self.step = Step::None;
+        self.prompt(cpu)
+    }
+
+    /// Reads commands from stdin until execution should resume. Returns false
+    /// if the user asked to quit.
+    pub fn prompt(&mut self, cpu: &mut Cpu) -> bool {
+        self.used = true;
+        let stdin = io::stdin();
+        let mut stdout = io::stdout();
+        print_registers(cpu, &mut stdout);
+
+        loop {
+            print!("(gbdb) ");
+            stdout.flush().expect("Failed to write to stdout");
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).expect("Failed to read from stdin") == 0 {
+                return false;
+            }
+            match self.command(cpu, &line, &mut stdout) {
+                Prompt::Stay => {}
+                Prompt::Resume => return true,
+                Prompt::Quit => return false,
+            }
+        }
+    }
+
+    fn command(&mut self, cpu: &mut Cpu, line: &str, out: &mut dyn Write) -> Prompt {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(Prompt::Stay),
+            ["c"] | ["continue"] => Ok(Prompt::Resume),
+            ["s"] | ["step"] => {
+                self.step = Step::Into(1);
+                Ok(Prompt::Resume)
+            }
+            ["s", count] | ["step", count] => match count.parse() {
+                Ok(count) if count > 0 => {
+                    self.step = Step::Into(count);
+                    Ok(Prompt::Resume)
+                }
+                _ => Err(format!("Invalid count: {}", count)),
+            },
+            ["n"] | ["next"] => {
+                self.step = Step::Over { depth: self.call_stack.frames().len() };
+                Ok(Prompt::Resume)
+            }
+            ["f"] | ["finish"] => match self.call_stack.frames().len() {
+                0 => Err("Not inside a call".to_string()),
+                depth => {
+                    self.step = Step::Out { depth };
+                    Ok(Prompt::Resume)
+                }
+            },
+            ["b", location] | ["break", location] => match Breakpoint::parse_with_symbols(location, cpu.mmu.cartridge.symbols()) {
+                Some(breakpoint) => {
+                    self.breakpoints.push(breakpoint);
+                    writeln!(out, "Breakpoint {} at {}", self.breakpoints.len() - 1, breakpoint).unwrap();
+                    Ok(Prompt::Stay)
+                }
+                None => Err(format!("Invalid breakpoint: {}", location)),
+            },
+            ["d", index] | ["delete", index] => match index.parse::<usize>() {
+                Ok(index) if index < self.breakpoints.len() => {
+                    self.breakpoints.remove(index);
+                    Ok(Prompt::Stay)
+                }
+                _ => Err(format!("No breakpoint {}", index)),
+            },
+            ["w", location] | ["watch", location] => match Watchpoint::parse(location) {
+                Some(watchpoint) => {
+                    cpu.mmu.watchpoints.add(watchpoint);
+                    let index = cpu.mmu.watchpoints.list().len() - 1;
+                    writeln!(out, "Watchpoint {} at {}", index, watchpoint).unwrap();
+                    Ok(Prompt::Stay)
+                }
+                None => Err(format!("Invalid watchpoint: {}", location)),
+            },
+            ["dw", index] => match index.parse::<usize>() {
+                Ok(index) if cpu.mmu.watchpoints.remove(index) => Ok(Prompt::Stay),
+                _ => Err(format!("No watchpoint {}", index)),
+            },
+            ["l"] | ["list"] => {
+                for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+                    writeln!(out, "Breakpoint {}: {}", index, breakpoint).unwrap();
+                }
+                for (index, watchpoint) in cpu.mmu.watchpoints.list().iter().enumerate() {
+                    writeln!(out, "Watchpoint {}: {}", index, watchpoint).unwrap();
+                }
+                Ok(Prompt::Stay)
+            }
+            ["bt"] | ["backtrace"] => {
+                self.print_backtrace(cpu, out);
+                Ok(Prompt::Stay)
+            }
+            ["cheats"] => {
+                for (index, cheat) in cpu.mmu.cartridge.cheats.list().iter().enumerate() {
+                    let state = if cheat.enabled { "on" } else { "off" };
+                    writeln!(out, "Cheat {} [{}]: {} {} ({})", index, state, cheat.text, cheat.description, cheat.code).unwrap();
+                }
+                Ok(Prompt::Stay)
+            }
+            ["cheat", "add", code, description @ ..] => {
+                if cpu.mmu.cartridge.cheats.add(code, &description.join(" ")) {
+                    save_cheats(cpu, out);
+                    Ok(Prompt::Stay)
+                } else {
+                    Err(format!("Invalid cheat code: {}", code))
+                }
+            }
+            ["cheat", action @ ("on" | "off" | "del"), index] => {
+                let cheats = &mut cpu.mmu.cartridge.cheats;
+                let found = match index.parse::<usize>() {
+                    Ok(index) if *action == "del" => cheats.remove(index),
+                    Ok(index) => cheats.set_enabled(index, *action == "on"),
+                    Err(_) => false,
+                };
+                if found {
+                    save_cheats(cpu, out);
+                    Ok(Prompt::Stay)
+                } else {
+                    Err(format!("No cheat {}", index))
+                }
+            }
+            ["search", "start"] => {
+                let search = RamSearch::new(Snapshot::take(&cpu.mmu));
+                writeln!(out, "{} addresses", search.candidates().len()).unwrap();
+                self.ram_search = Some(search);
+                Ok(Prompt::Stay)
+            }
+            ["search", args @ ..] => self.search(cpu, args, out).map(|_| Prompt::Stay),
+            ["r"] | ["regs"] => {
+                print_registers(cpu, out);
+                Ok(Prompt::Stay)
+            }
+            ["set", register, value] => set_register(cpu, register, value).map(|_| Prompt::Stay),
+            ["flag", flag, value] => set_flag(cpu, flag, value).map(|_| Prompt::Stay),
+            ["x", address] => dump_memory(cpu, address, "16", out).map(|_| Prompt::Stay),
+            ["x", address, count] => dump_memory(cpu, address, count, out).map(|_| Prompt::Stay),
+            ["q"] | ["quit"] => Ok(Prompt::Quit),
+            ["h"] | ["help"] => {
+                writeln!(out, "{}", HELP).unwrap();
+                Ok(Prompt::Stay)
+            }
+            _ => Err(format!("Unknown command: {}. Type 'help' for a list of commands.", line.trim())),
+        };
+
+        result.unwrap_or_else(|e| {
+            writeln!(out, "{}", e).unwrap();
+            Prompt::Stay
+        })
+    }
+
+    /// Handles `search` commands after `search start`.
+    fn search(&mut self, cpu: &mut Cpu, args: &[&str], out: &mut dyn Write) -> Result<(), String> {
+        let search = self.ram_search.as_mut().ok_or("No search running, use 'search start'")?;
+        let comparison = match args {
+            ["equal"] => Some(Comparison::Equal(None)),
+            ["equal", value] => Some(Comparison::Equal(Some(parse_byte(value)?))),
+            ["changed"] => Some(Comparison::Changed),
+            ["increased"] => Some(Comparison::Increased),
+            ["decreased"] => Some(Comparison::Decreased),
+            _ => None,
+        };
+        if let Some(comparison) = comparison {
+            search.narrow(Snapshot::take(&cpu.mmu), comparison);
+            writeln!(out, "{} addresses left", search.candidates().len()).unwrap();
+            if search.candidates().len() > SEARCH_LIST_LIMIT {
+                return Ok(());
+            }
+        }
+
+        match args {
+            ["freeze", index, value @ ..] | ["code", index, value @ ..] => {
+                let address = index
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| search.candidates().get(index).copied())
+                    .ok_or_else(|| format!("No address {}", index))?;
+                let value = match value {
+                    [] => search.value(address),
+                    [value] => parse_byte(value)?,
+                    _ => return Err(format!("Invalid value: {}", value.join(" "))),
+                };
+                let code = address.gameshark_code(value);
+                if args[0] == "freeze" {
+                    cpu.mmu.cartridge.cheats.add(&code, &format!("Freeze {}", address));
+                    writeln!(out, "Added cheat {}", code).unwrap();
+                    save_cheats(cpu, out);
+                } else {
+                    writeln!(out, "{}", code).unwrap();
+                }
+            }
+            _ if comparison.is_some() || args == ["list"] => {
+                for (index, address) in search.candidates().iter().take(SEARCH_LIST_LIMIT).enumerate() {
+                    writeln!(out, "{:>3}: {} = {:02x}", index, address, search.value(*address)).unwrap();
+                }
+                if search.candidates().len() > SEARCH_LIST_LIMIT {
+                    writeln!(out, "...").unwrap();
+                }
+            }
+            _ => return Err(format!("Unknown search command: {}", args.join(" "))),
+        }
+        Ok(())
+    }
+
+    /// Innermost frame first, starting with the current PC.
+    fn print_backtrace(&self, cpu: &Cpu, out: &mut dyn Write) {
+        writeln!(out, "#0  {}", format_pc(cpu, cpu.debug_pc())).unwrap();
+        let symbols = cpu.mmu.cartridge.symbols();
+        for (depth, frame) in self.call_stack.frames().iter().rev().enumerate() {
+            let location = format_location(symbols, frame.call_bank, frame.call_site);
+            let target = format_location(symbols, frame.target_bank, frame.target);
+            writeln!(out, "#{:<2} {}  -> {}", depth + 1, location, target).unwrap();
+        }
+    }
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    parse_hex(text)
+        .filter(|&value| value <= 0xff)
+        .map(|value| value as u8)
+        .ok_or_else(|| format!("Invalid value: {}", text))
+}
+
+pub(crate) fn parse_hex(text: &str) -> Option<usize> {
+    let text = text.trim_start_matches("0x").trim_start_matches('$');
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// ROM bank mapped at `address`, or `None` outside cartridge ROM.
+pub(crate) fn rom_bank(cpu: &Cpu, address: usize) -> Option<usize> {
+    if address < 0x8000 {
+        Some(cpu.mmu.cartridge.rom_bank(address))
+    } else {
+        None
+    }
+}
+
+/// Formats a PC as `bank:address` in cartridge ROM and `address` elsewhere,
+/// followed by the label it's in, if any.
+pub fn format_pc(cpu: &Cpu, pc: usize) -> String {
+    format_location(cpu.mmu.cartridge.symbols(), rom_bank(cpu, pc), pc)
+}
+
+fn format_location(symbols: &Symbols, bank: Option<usize>, address: usize) -> String {
+    let location = match bank {
+        Some(bank) => format!("{:02x}:{:04x}", bank, address),
+        None => format!("{:04x}", address),
+    };
+    match symbols.describe(bank, address) {
+        Some(label) => format!("{} ({})", location, label),
+        None => location,
+    }
+}
+
+fn print_registers(cpu: &Cpu, out: &mut dyn Write) {
+    let r = &cpu.registers;
+    let flag = |set: bool, name: char| if set { name } else { '-' };
+    let ime = match cpu.mmu.interrupt_controller.state {
+        InterruptState::Disabled => "off",
+        InterruptState::Scheduled => "scheduled",
+        InterruptState::Enabled => "on",
+    };
+    writeln!(
+        out,
+        "PC={} SP={:04x} AF={:04x} BC={:04x} DE={:04x} HL={:04x} [{}{}{}{}] IME={}",
+        format_pc(cpu, cpu.debug_pc()),
+        cpu.sp,
+        r.get_af(),
+        r.get_bc(),
+        r.get_de(),
+        r.get_hl(),
+        flag(r.f.z, 'Z'),
+        flag(r.f.n, 'N'),
+        flag(r.f.h, 'H'),
+        flag(r.f.c, 'C'),
+        ime
+    )
+    .unwrap();
+
+    let pc = cpu.debug_pc();
+    let symbols = cpu.mmu.cartridge.symbols();
+    let instruction = disassemble_with_labels(
+        pc,
+        |address| cpu.mmu.peek_byte(address),
+        |target| symbols.name(rom_bank(cpu, target), target).map(|name| name.to_string()),
+    );
+    let bytes: Vec<String> = (0..instruction.length)
+        .map(|i| format!("{:02x}", cpu.mmu.peek_byte((pc + i) & 0xffff)))
+        .collect();
+    writeln!(out, "  {:<9} {}", bytes.join(" "), instruction.text).unwrap();
+}
+
+/// Keeps the cheat file in step with changes made at the prompt.
+fn save_cheats(cpu: &Cpu, out: &mut dyn Write) {
+    let path = cpu.mmu.cartridge.cheat_path();
+    if let Err(e) = cpu.mmu.cartridge.cheats.save(&path) {
+        writeln!(out, "Failed to save cheats to {}: {}", path.display(), e).unwrap();
+    }
+}
+
+fn set_register(cpu: &mut Cpu, register: &str, value: &str) -> Result<(), String> {
+    let value = parse_hex(value).ok_or_else(|| format!("Invalid value: {}", value))?;
+    let byte = value as u8;
+    let word = value as u16;
+    let r = &mut cpu.registers;
+    match register {
+        "a" => r.a = byte,
+        "b" => r.b = byte,
+        "c" => r.c = byte,
+        "d" => r.d = byte,
+        "e" => r.e = byte,
+        "f" => r.f.set_from_u8(byte),
+        "h" => r.h = byte,
+        "l" => r.l = byte,
+        "af" => r.set_af(word),
+        "bc" => r.set_bc(word),
+        "de" => r.set_de(word),
+        "hl" => r.set_hl(word),
+        "sp" => cpu.sp = word as usize,
+        "pc" => cpu.set_pc(word as usize),
+        _ => return Err(format!("Unknown register: {}", register)),
+    }
+    Ok(())
+}
+
+fn set_flag(cpu: &mut Cpu, flag: &str, value: &str) -> Result<(), String> {
+    let value = match value {
+        "0" => false,
+        "1" => true,
+        _ => return Err(format!("Invalid flag value: {}", value)),
+    };
+    let f = &mut cpu.registers.f;
+    match flag {
+        "z" => f.z = value,
+        "n" => f.n = value,
+        "h" => f.h = value,
+        "c" => f.c = value,
+        _ => return Err(format!("Unknown flag: {}", flag)),
+    }
+    Ok(())
+}
+
+fn dump_memory(cpu: &Cpu, address: &str, count: &str, out: &mut dyn Write) -> Result<(), String> {
+    let address = parse_hex(address)
+        .filter(|&a| a <= 0xffff)
+        .ok_or_else(|| format!("Invalid address: {}", address))?;
+    let count: usize = count.parse().map_err(|_| format!("Invalid count: {}", count))?;
+    let end = address.saturating_add(count).min(0x10000);
+    for line_start in (address..end).step_by(16) {
+        let line_end = (line_start + 16).min(end);
+        let bytes: Vec<String> = (line_start..line_end)
+            .map(|a| format!("{:02x}", cpu.mmu.peek_byte(a)))
+            .collect();
+        writeln!(out, "{:04x}: {}", line_start, bytes.join(" ")).unwrap();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!(Breakpoint::parse("150"), Some(Breakpoint { bank: None, address: 0x150 }));
+        assert_eq!(Breakpoint::parse("0x4abc"), Some(Breakpoint { bank: None, address: 0x4abc }));
+        assert_eq!(Breakpoint::parse("1f:$4000"), Some(Breakpoint { bank: Some(0x1f), address: 0x4000 }));
+        assert_eq!(Breakpoint::parse("1f:"), None);
+        assert_eq!(Breakpoint::parse("xyz"), None);
+        assert_eq!(Breakpoint::parse("02:4000").unwrap().to_string(), "02:4000");
+
+        let symbols = Symbols::parse("00:0150 Main\n03:4abc Banked\n00:0200 add\n");
+        let parse = |text| Breakpoint::parse_with_symbols(text, &symbols);
+        assert_eq!(parse("Main"), Some(Breakpoint { bank: None, address: 0x150 }));
+        assert_eq!(parse("Banked"), Some(Breakpoint { bank: Some(3), address: 0x4abc }));
+        assert_eq!(parse("add"), Some(Breakpoint { bank: None, address: 0x200 }));
+        assert_eq!(parse("1f:4000"), Some(Breakpoint { bank: Some(0x1f), address: 0x4000 }));
+    }
+}
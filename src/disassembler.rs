@@ -0,0 +1,204 @@
+//! SM83 disassembler, decoding opcodes the same way as `Cpu::execute_cycle`.
+//! Output uses RGBDS syntax.
+
+use super::symbols::Symbols;
+
+const REG8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const REG16: [&str; 4] = ["bc", "de", "hl", "sp"];
+const REG16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const CB_SHIFTS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub length: usize,
+    pub text: String,
+}
+
+/// Disassembles the instruction at `address`, fetching bytes with `read`.
+pub fn disassemble<F: Fn(usize) -> u8>(address: usize, read: F) -> Instruction {
+    disassemble_with_labels(address, read, |_| None)
+}
+
+/// Like `disassemble`, but shows jump targets and memory operands as the
+/// names returned by `label`.
+pub fn disassemble_with_labels<F, L>(address: usize, read: F, label: L) -> Instruction
+where
+    F: Fn(usize) -> u8,
+    L: Fn(usize) -> Option<String>,
+{
+    let opcode = read(address);
+    let d8 = || read((address + 1) & 0xffff);
+    let d16 = || (read((address + 2) & 0xffff) as u16) << 8 | read((address + 1) & 0xffff) as u16;
+    let r8_target = || (address as i32 + 2 + d8() as i8 as i32) as u16;
+    let target = |target: u16| label(target as usize).unwrap_or_else(|| format!("${:04x}", target));
+    let a16 = || target(d16());
+    let high = || label(0xff00 | d8() as usize).unwrap_or_else(|| format!("$ff{:02x}", d8()));
+
+    let r8 = REG8[(opcode & 0x07) as usize];
+    let r8_dest = REG8[((opcode >> 3) & 0x07) as usize];
+    let r16 = REG16[((opcode >> 4) & 0x03) as usize];
+    let r16_stack = REG16_STACK[((opcode >> 4) & 0x03) as usize];
+    let condition = CONDITIONS[((opcode >> 3) & 0x03) as usize];
+    let alu = ALU[((opcode >> 3) & 0x07) as usize];
+
+    let (length, text) = match opcode {
+        0x00 => (1, "nop".to_string()),
+        0x01 | 0x11 | 0x21 | 0x31 => (3, format!("ld {}, ${:04x}", r16, d16())),
+        0x02 => (1, "ld [bc], a".to_string()),
+        0x03 | 0x13 | 0x23 | 0x33 => (1, format!("inc {}", r16)),
+        0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => (1, format!("inc {}", r8_dest)),
+        0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => (1, format!("dec {}", r8_dest)),
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => (2, format!("ld {}, ${:02x}", r8_dest, d8())),
+        0x07 => (1, "rlca".to_string()),
+        0x08 => (3, format!("ld [{}], sp", a16())),
+        0x09 | 0x19 | 0x29 | 0x39 => (1, format!("add hl, {}", r16)),
+        0x0A => (1, "ld a, [bc]".to_string()),
+        0x0B | 0x1B | 0x2B | 0x3B => (1, format!("dec {}", r16)),
+        0x0F => (1, "rrca".to_string()),
+        0x10 => (1, "stop".to_string()),
+        0x12 => (1, "ld [de], a".to_string()),
+        0x17 => (1, "rla".to_string()),
+        0x18 => (2, format!("jr {}", target(r8_target()))),
+        0x1A => (1, "ld a, [de]".to_string()),
+        0x1F => (1, "rra".to_string()),
+        0x20 | 0x28 | 0x30 | 0x38 => (2, format!("jr {}, {}", condition, target(r8_target()))),
+        0x22 => (1, "ld [hl+], a".to_string()),
+        0x27 => (1, "daa".to_string()),
+        0x2A => (1, "ld a, [hl+]".to_string()),
+        0x2F => (1, "cpl".to_string()),
+        0x32 => (1, "ld [hl-], a".to_string()),
+        0x37 => (1, "scf".to_string()),
+        0x3A => (1, "ld a, [hl-]".to_string()),
+        0x3F => (1, "ccf".to_string()),
+        0x76 => (1, "halt".to_string()),
+        0x40..=0x7F => (1, format!("ld {}, {}", r8_dest, r8)),
+        0x80..=0xBF => (1, format!("{} a, {}", alu, r8)),
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => (1, format!("ret {}", condition)),
+        0xC1 | 0xD1 | 0xE1 | 0xF1 => (1, format!("pop {}", r16_stack)),
+        0xC2 | 0xCA | 0xD2 | 0xDA => (3, format!("jp {}, {}", condition, a16())),
+        0xC3 => (3, format!("jp {}", a16())),
+        0xC4 | 0xCC | 0xD4 | 0xDC => (3, format!("call {}, {}", condition, a16())),
+        0xC5 | 0xD5 | 0xE5 | 0xF5 => (1, format!("push {}", r16_stack)),
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => (2, format!("{} a, ${:02x}", alu, d8())),
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => (1, format!("rst ${:02x}", opcode & 0x38)),
+        0xC9 => (1, "ret".to_string()),
+        0xCD => (3, format!("call {}", a16())),
+        0xD9 => (1, "reti".to_string()),
+        0xE0 => (2, format!("ldh [{}], a", high())),
+        0xE2 => (1, "ldh [c], a".to_string()),
+        0xE8 => (2, format!("add sp, {}", d8() as i8)),
+        0xE9 => (1, "jp hl".to_string()),
+        0xEA => (3, format!("ld [{}], a", a16())),
+        0xF0 => (2, format!("ldh a, [{}]", high())),
+        0xF2 => (1, "ldh a, [c]".to_string()),
+        0xF3 => (1, "di".to_string()),
+        0xF8 => (2, format!("ld hl, sp{:+}", d8() as i8)),
+        0xF9 => (1, "ld sp, hl".to_string()),
+        0xFA => (3, format!("ld a, [{}]", a16())),
+        0xFB => (1, "ei".to_string()),
+        0xCB => (2, disassemble_cb(d8())),
+        _ => (1, format!("db ${:02x}", opcode)),
+    };
+
+    Instruction { length, text }
+}
+
+fn disassemble_cb(opcode: u8) -> String {
+    let r8 = REG8[(opcode & 0x07) as usize];
+    let bit = (opcode >> 3) & 0x07;
+    match opcode {
+        0x00..=0x3F => format!("{} {}", CB_SHIFTS[bit as usize], r8),
+        0x40..=0x7F => format!("bit {}, {}", bit, r8),
+        0x80..=0xBF => format!("res {}, {}", bit, r8),
+        0xC0..=0xFF => format!("set {}, {}", bit, r8),
+    }
+}
+
+/// Disassembles a whole ROM bank as `bank:address  bytes  instruction` lines,
+/// with a `Label:` line before each address in `symbols`. Instructions running
+/// past the end of the bank are cut off.
+pub fn disassemble_bank(rom: &[u8], bank: usize, symbols: &Symbols) -> Vec<String> {
+    let base = if bank == 0 { 0x0000 } else { 0x4000 };
+    let bank_start = bank * 0x4000;
+    let read = |address: usize| {
+        if (base..base + 0x4000).contains(&address) {
+            rom.get(bank_start + address - base).copied().unwrap_or(0xff)
+        } else {
+            0xff
+        }
+    };
+
+    let mut lines = vec![];
+    let mut address = base;
+    while address < base + 0x4000 {
+        if let Some(name) = symbols.name(Some(bank), address) {
+            lines.push(format!("{}:", name));
+        }
+        let instruction = disassemble_with_labels(address, read, |target| {
+            let target_bank = match target {
+                0x0000..=0x3fff => Some(0),
+                0x4000..=0x7fff => Some(bank),
+                _ => None,
+            };
+            symbols.name(target_bank, target).map(|name| name.to_string())
+        });
+        let bytes: Vec<String> = (address..address + instruction.length)
+            .map(|a| format!("{:02x}", read(a)))
+            .collect();
+        lines.push(format!("{:02x}:{:04x}  {:<9} {}", bank, address, bytes.join(" "), instruction.text));
+        address += instruction.length;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn disassemble_bytes(bytes: &[u8]) -> Instruction {
+        disassemble(0x150, |address| bytes.get(address - 0x150).copied().unwrap_or(0))
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let cases: [(&[u8], usize, &str); 12] = [
+            (&[0x00], 1, "nop"),
+            (&[0x31, 0xfe, 0xff], 3, "ld sp, $fffe"),
+            (&[0x36, 0x12], 2, "ld [hl], $12"),
+            (&[0x20, 0xfe], 2, "jr nz, $0150"),
+            (&[0x7e], 1, "ld a, [hl]"),
+            (&[0xae], 1, "xor a, [hl]"),
+            (&[0xe0, 0x44], 2, "ldh [$ff44], a"),
+            (&[0xf8, 0xfe], 2, "ld hl, sp-2"),
+            (&[0xcd, 0x34, 0x12], 3, "call $1234"),
+            (&[0xdf], 1, "rst $18"),
+            (&[0xd3], 1, "db $d3"),
+            (&[0xcb, 0x7c], 2, "bit 7, h"),
+        ];
+        for (bytes, length, text) in cases.iter() {
+            assert_eq!(disassemble_bytes(bytes), Instruction { length: *length, text: text.to_string() });
+        }
+    }
+
+    #[test]
+    fn test_disassemble_labels() {
+        let symbols = Symbols::parse("00:0150 Main\n00:ff80 hCounter\n");
+        let rom = [0xf0, 0x80, 0x18, 0xfc];
+        let mut full = vec![0; 0x150];
+        full.extend_from_slice(&rom);
+        let lines = disassemble_bank(&full, 0, &symbols);
+        assert_eq!(lines[0x150], "Main:");
+        assert!(lines[0x151].ends_with("ldh a, [hCounter]"));
+        assert!(lines[0x152].ends_with("jr Main"));
+    }
+
+    #[test]
+    fn test_disassemble_cb() {
+        assert_eq!(disassemble_cb(0x11), "rl c");
+        assert_eq!(disassemble_cb(0x36), "swap [hl]");
+        assert_eq!(disassemble_cb(0x86), "res 0, [hl]");
+        assert_eq!(disassemble_cb(0xff), "set 7, a");
+    }
+}
@@ -1,4 +1,4 @@
-use super::{SCALE_FACTOR, SCREEN_HEIGHT, SCREEN_WIDTH};
+use super::{SCALE_FACTOR, SCREEN_HEIGHT};
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 
@@ -7,12 +7,13 @@ pub struct Display {
 }
 
 impl Display {
-    pub fn new(sdl: &sdl2::Sdl) -> Self {
+    /// Opens a window for frames `width` pixels wide.
+    pub fn new(sdl: &sdl2::Sdl, width: usize) -> Self {
         let sdl_video = sdl.video().unwrap();
         let window = sdl_video
             .window(
                 "Gameboy Emulator",
-                SCREEN_WIDTH as u32 * SCALE_FACTOR,
+                width as u32 * SCALE_FACTOR,
                 SCREEN_HEIGHT as u32 * SCALE_FACTOR,
             )
             .build()
@@ -0,0 +1,94 @@
+use super::Joypad;
+
+/// Emulator-level toggles driven by the user rather than the emulated game.
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct Controls {
+    pub quit: bool,
+    pub pause: bool,
+    pub mute: bool,
+    pub run_2x: bool,
+    /// Held to step backwards through rewind snapshots.
+    pub rewind: bool,
+    /// Save or load the given slot. Cleared on the next poll.
+    pub save_state: Option<u8>,
+    pub load_state: Option<u8>,
+    /// Open the debugger prompt before the next instruction. Cleared on the next poll.
+    pub debug_break: bool,
+    /// Turns all cheats off while set.
+    pub disable_cheats: bool,
+}
+
+/// Receives completed frames as RGB24, `SCREEN_WIDTH * SCREEN_HEIGHT * 3` bytes.
+pub trait VideoSink {
+    fn present_frame(&mut self, frame: &[u8]);
+}
+
+/// Receives interleaved stereo samples at `sound::SAMPLE_RATE`.
+pub trait AudioSink {
+    /// Whether the sink can take another chunk right now.
+    fn audio_ready(&self) -> bool {
+        true
+    }
+
+    fn queue_audio(&mut self, samples: &[f32]);
+}
+
+pub trait InputSource {
+    /// Updates the joypad from pending input and returns the current controls.
+    fn poll_inputs(&mut self, joypad: &mut Joypad) -> Controls;
+}
+
+/// Everything `GameBoy::run` needs to talk to the outside world.
+pub trait Frontend: VideoSink + AudioSink + InputSource {
+    /// Throttle emulation to the audio sink, i.e. run at real-time speed.
+    fn limit_speed(&self) -> bool {
+        false
+    }
+}
+
+/// Discards all output and never presses a button.
+#[derive(Default)]
+pub struct NullFrontend;
+
+impl VideoSink for NullFrontend {
+    fn present_frame(&mut self, _frame: &[u8]) {}
+}
+
+impl AudioSink for NullFrontend {
+    fn queue_audio(&mut self, _samples: &[f32]) {}
+}
+
+impl InputSource for NullFrontend {
+    fn poll_inputs(&mut self, _joypad: &mut Joypad) -> Controls {
+        Controls::default()
+    }
+}
+
+impl Frontend for NullFrontend {}
+
+/// Like `NullFrontend`, but keeps the most recent frame for inspection.
+#[derive(Default)]
+pub struct HeadlessFrontend {
+    pub frame: Vec<u8>,
+    pub frame_count: u64,
+}
+
+impl VideoSink for HeadlessFrontend {
+    fn present_frame(&mut self, frame: &[u8]) {
+        self.frame.clear();
+        self.frame.extend_from_slice(frame);
+        self.frame_count += 1;
+    }
+}
+
+impl AudioSink for HeadlessFrontend {
+    fn queue_audio(&mut self, _samples: &[f32]) {}
+}
+
+impl InputSource for HeadlessFrontend {
+    fn poll_inputs(&mut self, _joypad: &mut Joypad) -> Controls {
+        Controls::default()
+    }
+}
+
+impl Frontend for HeadlessFrontend {}
@@ -0,0 +1,541 @@
+use super::frontend::{Controls, Frontend};
+use super::cheats::Cheats;
+use super::debugger::{self, Debugger};
+use super::gdb::{GdbAction, GdbStub};
+use super::mmu::DmaType;
+use super::movie::{Movie, MovieError, MovieMode, MovieSession, MovieStart};
+use super::profiler::Profiler;
+use super::rewind::Rewind;
+use super::save_state::{self, SaveStateError};
+use super::serial::LinkCable;
+use super::trace::Trace;
+use super::watchpoints::WatchHit;
+use super::{Cpu, CYCLES_PER_FRAME, DEBUG};
+use nanoserde::{DeBin, DeBinErr, SerBin};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BENCHMARK: bool = false;
+const BENCHMARK_COUNT: u32 = 10_000_000;
+
+/// How often to check for transfers clocked by the other end of the link.
+const LINK_POLL_CYCLES: u32 = 1024;
+
+pub struct GameBoy {
+    pub cpu: Cpu,
+    cycle_count: u32,
+    controls: Controls,
+    rewind: Option<Rewind>,
+    movie: Option<MovieSession>,
+    pub debugger: Debugger,
+    trace: Option<Trace>,
+    gdb: Option<GdbStub>,
+    profiler: Option<Profiler>,
+    link: Option<Box<dyn LinkCable>>,
+    /// Emulated time for the link cable, at 8MHz.
+    link_time: u64,
+}
+
+impl GameBoy {
+    /// Runs `boot_rom` first if given, otherwise starts the cartridge directly.
+    pub fn new<P: AsRef<Path>>(rom_path: P, dmg_mode: bool, boot_rom: Option<Vec<u8>>) -> Self {
+        GameBoy {
+            cpu: Cpu::new(rom_path, dmg_mode, boot_rom),
+            cycle_count: 0,
+            controls: Controls::default(),
+            rewind: None,
+            movie: None,
+            debugger: Debugger::new(),
+            trace: None,
+            gdb: None,
+            profiler: None,
+            link: None,
+            link_time: 0,
+        }
+    }
+
+    /// Logs every executed instruction to `path` in Gameboy Doctor format,
+    /// optionally with symbols appended.
+    pub fn trace_to<P: AsRef<Path>>(&mut self, path: P, symbols: bool) -> std::io::Result<()> {
+        self.trace = Some(Trace::create(path, symbols)?);
+        Ok(())
+    }
+
+    /// Waits for a GDB remote protocol client on `port` and hands it control.
+    pub fn attach_gdb(&mut self, port: u16) -> std::io::Result<()> {
+        self.gdb = Some(GdbStub::listen(port)?);
+        Ok(())
+    }
+
+    /// Plugs in a link cable to another Game Boy.
+    pub fn connect_link(&mut self, link: Box<dyn LinkCable>) {
+        self.link = Some(link);
+    }
+
+    /// Counts the cycles spent at each PC from now on.
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Whether the frontend or the debugger asked to quit.
+    pub fn quit_requested(&self) -> bool {
+        self.controls.quit
+    }
+
+    pub fn paused(&self) -> bool {
+        self.controls.pause
+    }
+
+    pub fn cycle_count(&self) -> u32 {
+        self.cycle_count
+    }
+
+    pub fn set_run_2x(&mut self, run_2x: bool) {
+        self.cpu.mmu.sound_controller.set_run_2x(run_2x);
+    }
+
+    /// Keeps snapshots covering the last `seconds` of gameplay for rewinding.
+    /// Zero disables rewinding.
+    pub fn enable_rewind(&mut self, seconds: u32) {
+        self.rewind = if seconds > 0 { Some(Rewind::new(seconds)) } else { None };
+    }
+
+    /// Forgets the rewind history, e.g. when jumping to another timeline.
+    fn clear_rewind(&mut self) {
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+    }
+
+    /// Starts recording input from the current state. The cheats in effect
+    /// are recorded too, and can't be switched off until recording stops.
+    pub fn record_movie(&mut self, start: MovieStart) {
+        let movie = Movie {
+            rom_crc32: self.cpu.mmu.cartridge.rom_crc32(),
+            dmg_mode: self.cpu.mmu.dmg_mode,
+            cheats: self.cpu.mmu.cartridge.cheats.enabled_codes(),
+            start,
+            state: self.save_state(),
+            inputs: vec![],
+        };
+        self.movie = Some(MovieSession::new(movie, MovieMode::Recording));
+        self.clear_rewind();
+    }
+
+    /// Loads the movie's start state and replays its input with the movie's
+    /// cheats in place of the current ones. The `GameBoy` must have been
+    /// created with the movie's `dmg_mode`.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+        if movie.rom_crc32 != self.cpu.mmu.cartridge.rom_crc32() {
+            return Err(MovieError::WrongRom);
+        }
+        let cheats = Cheats::from_codes(&movie.cheats)
+            .ok_or_else(|| MovieError::Corrupt("invalid cheat code".to_string()))?;
+        self.load_state(&movie.state)?;
+        let mut session = MovieSession::new(movie, MovieMode::Playing);
+        session.cheats = Some(std::mem::replace(&mut self.cpu.mmu.cartridge.cheats, cheats));
+        self.movie = Some(session);
+        self.clear_rewind();
+        Ok(())
+    }
+
+    /// Stops recording or playback, returning the movie. Cheats replaced for
+    /// playback are restored.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        let mut session = self.movie.take()?;
+        if let Some(cheats) = session.cheats.take() {
+            self.cpu.mmu.cartridge.cheats = cheats;
+        }
+        self.clear_rewind();
+        let (buttons, directions) = session.input.get_values();
+        self.cpu.mmu.joypad.set_values(buttons, directions);
+        Some(session.movie)
+    }
+
+    pub fn movie_mode(&self) -> Option<MovieMode> {
+        self.movie.as_ref().map(|session| session.mode)
+    }
+
+    pub fn poll_inputs<F: Frontend>(&mut self, frontend: &mut F) {
+        let joypad = match self.movie.as_mut() {
+            Some(session) => &mut session.input,
+            None => &mut self.cpu.mmu.joypad,
+        };
+        let quit = self.controls.quit;
+        self.controls = frontend.poll_inputs(joypad);
+        self.controls.quit |= quit;
+        self.set_run_2x(self.controls.run_2x);
+        self.cpu.mmu.sound_controller.set_mute(self.controls.mute);
+        // Movies keep the cheats they started with
+        if self.movie.is_none() {
+            self.cpu.mmu.cartridge.cheats.active = !self.controls.disable_cheats;
+        }
+        if self.controls.debug_break {
+            self.debugger.request_break();
+        }
+        if let Some(gdb) = self.gdb.as_mut() {
+            if let Err(e) = gdb.poll_interrupt() {
+                println!("GDB disconnected: {}", e);
+                self.gdb = None;
+            }
+        }
+
+        if let Some(slot) = self.controls.save_state {
+            match self.save_state_to_slot(slot) {
+                Ok(path) => println!("Saved state to {}", path.display()),
+                Err(e) => println!("Failed to save state to slot {}: {}", slot, e),
+            }
+        }
+        if let Some(slot) = self.controls.load_state {
+            if self.movie.is_some() {
+                println!("Cannot load state while a movie is recording or playing");
+            } else {
+                match self.load_state_from_slot(slot) {
+                    Ok(path) => println!("Loaded state from {}", path.display()),
+                    Err(e) => println!("Failed to load state from slot {}: {}", slot, e),
+                }
+            }
+        }
+    }
+
+    /// Serializes the whole machine, excluding the ROM itself.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut output = vec![];
+        let mmu = &self.cpu.mmu;
+        save_state::write_header(&mut output, mmu.cartridge.rom_crc32(), mmu.mapped_boot_rom());
+        self.save_state_body(&mut output);
+        output
+    }
+
+    /// Restores a state made by `save_state`. On error the machine is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut offset = 0;
+        let mmu = &self.cpu.mmu;
+        save_state::read_header(&mut offset, data, mmu.cartridge.rom_crc32(), mmu.boot_rom_crc32())?;
+
+        let mut backup = vec![];
+        self.save_state_body(&mut backup);
+
+        let result = match self.load_state_body(&mut offset, data) {
+            Ok(()) if offset != data.len() => Err(SaveStateError::Corrupt(format!(
+                "{} unexpected trailing bytes",
+                data.len() - offset
+            ))),
+            Ok(()) => Ok(()),
+            Err(e) => Err(e.into()),
+        };
+
+        if result.is_err() {
+            self.load_state_body(&mut 0, &backup)
+                .expect("Failed to restore state after failed load");
+        }
+        result
+    }
+
+    fn save_state_body(&self, output: &mut Vec<u8>) {
+        self.cycle_count.ser_bin(output);
+        self.cpu.save_state(output);
+    }
+
+    fn load_state_body(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.cycle_count = DeBin::de_bin(offset, data)?;
+        self.cpu.load_state(offset, data)
+    }
+
+    pub fn save_state_to_slot(&self, slot: u8) -> Result<PathBuf, SaveStateError> {
+        let path = self.cpu.mmu.cartridge.state_path(slot);
+        std::fs::write(&path, self.save_state())?;
+        Ok(path)
+    }
+
+    pub fn load_state_from_slot(&mut self, slot: u8) -> Result<PathBuf, SaveStateError> {
+        let path = self.cpu.mmu.cartridge.state_path(slot);
+        let data = std::fs::read(&path)?;
+        self.load_state(&data)?;
+        self.clear_rewind();
+        Ok(path)
+    }
+
+    /// Runs one 4MHz cycle and forwards any completed frame to the frontend.
+    pub fn step<F: Frontend>(&mut self, frontend: &mut F) {
+        if self.cycle_count.is_multiple_of(4096) {
+            self.poll_inputs(frontend);
+        }
+
+        if let Some(session) = self.movie.as_mut() {
+            if let Some((buttons, directions)) = session.tick() {
+                self.cpu.mmu.joypad.set_values(buttons, directions);
+            } else if session.finished() {
+                println!("Movie playback finished");
+                self.stop_movie();
+            }
+        }
+
+        self.execute_cycle();
+
+        if let Some(frame) = self.cpu.mmu.ppu.take_frame() {
+            frontend.present_frame(frame);
+            self.cpu.mmu.apply_cheats();
+            if self.rewind.as_mut().is_some_and(|rewind| rewind.frame_done()) {
+                let state = self.save_state();
+                self.rewind.as_mut().unwrap().push(state);
+            }
+        }
+    }
+
+    /// Restores the newest rewind snapshot, presents the frame that follows it
+    /// and queues the audio recorded after it in reverse. Returns false once
+    /// there is nothing left to rewind. Movies can't be rewound.
+    pub fn rewind_step<F: Frontend>(&mut self, frontend: &mut F) -> bool {
+        if self.movie.is_some() {
+            return false;
+        }
+        let (state, audio) = match self.rewind.as_mut().and_then(|rewind| rewind.pop()) {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.load_state(&state).expect("Failed to load rewind snapshot");
+
+        // The frame already ran once, so its trace lines and link traffic
+        // aren't repeated
+        let trace = self.trace.take();
+        let link = self.link.take();
+        let profiler = self.profiler.take();
+
+        // Snapshots are taken on frame boundaries, so this normally runs one
+        // frame. The limit covers the LCD being off.
+        for _ in 0..CYCLES_PER_FRAME * 2 {
+            self.execute_cycle();
+            while self.cpu.mmu.sound_controller.take_audio().is_some() {}
+            if let Some(frame) = self.cpu.mmu.ppu.take_frame() {
+                frontend.present_frame(frame);
+                break;
+            }
+        }
+        self.trace = trace;
+        self.link = link;
+        self.profiler = profiler;
+
+        if !self.controls.mute {
+            frontend.queue_audio(&audio);
+        }
+        true
+    }
+
+    fn queue_audio<F: Frontend>(&mut self, frontend: &mut F) {
+        if frontend.audio_ready() {
+            if let Some(samples) = self.cpu.mmu.sound_controller.take_audio() {
+                frontend.queue_audio(samples);
+                if let Some(rewind) = self.rewind.as_mut() {
+                    rewind.record_audio(samples);
+                }
+            }
+        }
+    }
+
+    /// Runs until the frontend asks to quit, then saves the cartridge.
+    pub fn run<F: Frontend>(&mut self, frontend: &mut F) {
+        let limit_speed = frontend.limit_speed() && !BENCHMARK && !DEBUG;
+        let mut benchmark_time = Instant::now();
+        let mut count = 0;
+
+        while !self.controls.quit {
+            if self.controls.rewind {
+                if !limit_speed || frontend.audio_ready() {
+                    if !self.rewind_step(frontend) {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                } else {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                self.poll_inputs(frontend);
+            } else if self.controls.pause {
+                self.poll_inputs(frontend);
+                thread::sleep(Duration::from_millis(10));
+            } else if self.cpu.mmu.sound_controller.buffer_full() {
+                if limit_speed {
+                    self.queue_audio(frontend);
+                    thread::sleep(Duration::from_millis(1));
+                } else if let Some(samples) = self.cpu.mmu.sound_controller.take_audio() {
+                    if frontend.audio_ready() {
+                        frontend.queue_audio(samples);
+                    }
+                    if let Some(rewind) = self.rewind.as_mut() {
+                        rewind.record_audio(samples);
+                    }
+                }
+            } else {
+                self.step(frontend);
+                count += 1;
+                if count == BENCHMARK_COUNT {
+                    let now = Instant::now();
+                    if BENCHMARK {
+                        println!(
+                            "Benchmark time: {}ms",
+                            now.duration_since(benchmark_time).as_millis()
+                        );
+                    }
+                    benchmark_time = now;
+                    count = 0;
+                }
+            }
+        }
+
+        self.cpu.mmu.cartridge.save();
+    }
+
+    pub fn execute_cycle(&mut self) {
+        let double_speed = self.cpu.mmu.double_speed;
+        // STOP freezes everything but the joypad, the link port and audio
+        let stopped = self.cpu.stopped;
+
+        // RTC advances on emulated time, so it runs fast along with run_2x
+        let update_rtc_cycle = if double_speed { 131072 } else { 65536 };
+        if self.cycle_count.is_multiple_of(update_rtc_cycle) {
+            self.cpu.mmu.cartridge.update_rtc(15_625);
+        }
+
+        // PPU runs at 4MHz always
+        if !stopped && (!double_speed || self.cycle_count.is_multiple_of(2)) {
+            self.cpu.mmu.ppu.execute_cycle(&mut self.cpu.mmu.interrupt_controller);
+        }
+
+        // Timer runs at 4MHz or 8MHz (every cycle)
+        if !stopped {
+            self.cpu.mmu.timer.execute_cycle(&mut self.cpu.mmu.interrupt_controller, &mut self.cpu.mmu.sound_controller, double_speed);
+        }
+
+        // Serial clock scales with double speed too (every cycle)
+        if self.cpu.mmu.serial_link.execute_cycle() {
+            let incoming = match self.link.as_mut().map(|link| link.receive()) {
+                Some(Ok(byte)) => byte,
+                Some(Err(e)) => {
+                    self.disconnect_link(e);
+                    0xff
+                }
+                None => 0xff,
+            };
+            self.cpu.mmu.serial_link.finish_transfer(incoming, &mut self.cpu.mmu.interrupt_controller);
+        }
+        if let Some(link) = self.link.as_mut() {
+            self.link_time += if double_speed { 1 } else { 2 };
+            if self.cycle_count.is_multiple_of(LINK_POLL_CYCLES) {
+                let mmu = &mut self.cpu.mmu;
+                if let Err(e) = link.poll(&mut mmu.serial_link, &mut mmu.interrupt_controller, self.link_time) {
+                    self.disconnect_link(e);
+                }
+            }
+        }
+
+        // Cpu runs at 1MHz or 2MHz (4 cycles)
+        if self.cycle_count.is_multiple_of(4) {
+            if self.cpu.mmu.joypad.poll_interrupt() {
+                self.cpu.mmu.interrupt_controller.interrupt_flag |= 0x10;
+            }
+            let locked = self.cpu.locked;
+            self.cpu.execute_cycle();
+            if self.cpu.locked && !locked {
+                self.locked_up();
+            }
+            if let Some((byte, control)) = self.cpu.mmu.serial_link.take_outgoing() {
+                let time = self.link_time;
+                if let Err(e) = self.link.as_mut().map_or(Ok(()), |link| link.send(byte, control, time)) {
+                    self.disconnect_link(e);
+                }
+            }
+            if let Some(hit) = self.cpu.mmu.watchpoints.take_hit() {
+                self.watchpoint_hit(hit, false);
+            }
+            if self.cpu.at_instruction_start() {
+                if let Some(profiler) = self.profiler.as_mut() {
+                    profiler.instruction_start(&self.cpu, self.cycle_count);
+                }
+                if let Some(gdb) = self.gdb.as_mut() {
+                    if gdb.should_stop(&self.cpu) {
+                        self.serve_gdb(None);
+                    }
+                } else if self.debugger.should_break(&self.cpu) {
+                    self.controls.quit |= !self.debugger.prompt(&mut self.cpu);
+                }
+                if let Some(trace) = self.trace.as_mut() {
+                    trace.log(&self.cpu);
+                }
+            }
+        }
+
+        // OAM transfer runs at 1MHz or 2MHz (4 cycles)
+        // Other DMAs always run at 2MHz
+        let dma_cycles = match self.cpu.mmu.dma_config.dma_type {
+            DmaType::Oam => 4,
+            _ if !double_speed => 2,
+            _                  => 4,
+        };
+        if !stopped && self.cycle_count.is_multiple_of(dma_cycles) {
+            self.cpu.mmu.execute_cycle();
+            if let Some(hit) = self.cpu.mmu.watchpoints.take_hit() {
+                self.watchpoint_hit(hit, true);
+            }
+        }
+
+        // APU always runs at 4MHz
+        if !double_speed || self.cycle_count.is_multiple_of(2) {
+            self.cpu.mmu.sound_controller.execute_cycle();
+        }
+
+        self.cycle_count = self.cycle_count.wrapping_add(1);
+    }
+
+    /// The CPU hit an illegal opcode and won't run again, though the PPU and
+    /// APU carry on. Saves right away so battery RAM isn't lost if the user
+    /// gives up on this session.
+    fn locked_up(&mut self) {
+        println!(
+            "CPU locked up by illegal opcode {:02x} at PC {}",
+            self.cpu.opcode1,
+            debugger::format_pc(&self.cpu, self.cpu.debug_pc())
+        );
+        self.cpu.mmu.cartridge.save();
+        if self.gdb.is_some() {
+            self.serve_gdb(None);
+        } else {
+            self.controls.quit |= !self.debugger.locked_up(&mut self.cpu);
+        }
+    }
+
+    fn disconnect_link(&mut self, error: std::io::Error) {
+        println!("Link cable disconnected: {}", error);
+        self.link = None;
+    }
+
+    fn watchpoint_hit(&mut self, hit: WatchHit, dma: bool) {
+        if self.gdb.is_some() {
+            self.serve_gdb(Some(hit));
+        } else {
+            self.controls.quit |= !self.debugger.watchpoint_hit(&mut self.cpu, hit, dma);
+        }
+    }
+
+    fn serve_gdb(&mut self, hit: Option<WatchHit>) {
+        let gdb = self.gdb.as_mut().expect("No GDB client attached");
+        match gdb.serve(&mut self.cpu, hit) {
+            Ok(GdbAction::Resume) => {}
+            Ok(GdbAction::Detach) => {
+                println!("GDB detached");
+                self.gdb = None;
+            }
+            Ok(GdbAction::Kill) => {
+                self.gdb = None;
+                self.controls.quit = true;
+            }
+            Err(e) => {
+                println!("GDB disconnected: {}", e);
+                self.gdb = None;
+            }
+        }
+    }
+}
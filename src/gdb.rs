@@ -0,0 +1,370 @@
+//! GDB remote serial protocol stub.
+//!
+//! Registers are exposed as six 16-bit little-endian values in the order
+//! AF, BC, DE, HL, SP, PC. Memory goes through `Mmu`, so it sees the currently
+//! mapped banks.
+
+use super::debugger::parse_hex;
+use super::watchpoints::{WatchHit, WatchKind, Watchpoint};
+use super::Cpu;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const REGISTER_COUNT: usize = 6;
+
+/// Signal reported for every stop.
+const SIGTRAP: &str = "05";
+
+/// What the emulator should do after the stub hands back control.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GdbAction {
+    Resume,
+    Detach,
+    Kill,
+}
+
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: Vec<usize>,
+    step: bool,
+    stop_requested: bool,
+    /// Whether gdb is waiting for a stop reply after `c` or `s`.
+    running: bool,
+}
+
+impl GdbStub {
+    /// Waits for a debugger to connect on `127.0.0.1:port`. The target starts
+    /// stopped before its next instruction.
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for GDB to connect on 127.0.0.1:{}", port);
+        let (stream, address) = listener.accept()?;
+        println!("GDB connected from {}", address);
+        stream.set_nodelay(true)?;
+
+        Ok(GdbStub {
+            stream,
+            breakpoints: vec![],
+            step: false,
+            stop_requested: true,
+            running: false,
+        })
+    }
+
+    /// Called at every instruction start.
+    pub fn should_stop(&mut self, cpu: &Cpu) -> bool {
+        if self.step || self.stop_requested || self.breakpoints.contains(&cpu.debug_pc()) {
+            self.step = false;
+            self.stop_requested = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Checks for a Ctrl-C from gdb without blocking. Returns an error if the
+    /// connection dropped.
+    pub fn poll_interrupt(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => {
+                if byte[0] == 0x03 {
+                    self.stop_requested = true;
+                }
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reports a stop and serves requests until gdb resumes the target.
+    pub fn serve(&mut self, cpu: &mut Cpu, hit: Option<WatchHit>) -> io::Result<GdbAction> {
+        if self.running {
+            self.running = false;
+            self.send(&stop_reply(hit))?;
+        }
+
+        loop {
+            let command = match self.read_packet()? {
+                Packet::Command(command) => command,
+                Packet::Interrupt => continue,
+            };
+            let response = match command.as_bytes().first() {
+                Some(b'c') | Some(b's') => {
+                    if let Some(address) = parse_hex(&command[1..]).filter(|&a| a <= 0xffff) {
+                        cpu.set_pc(address);
+                    }
+                    self.step = command.starts_with('s');
+                    self.running = true;
+                    return Ok(GdbAction::Resume);
+                }
+                Some(b'D') => {
+                    self.send("OK")?;
+                    return Ok(GdbAction::Detach);
+                }
+                Some(b'k') => return Ok(GdbAction::Kill),
+                _ => self.handle(cpu, &command),
+            };
+            self.send(&response)?;
+        }
+    }
+
+    fn handle(&mut self, cpu: &mut Cpu, command: &str) -> String {
+        // Empty and unknown packets get an empty reply
+        let Some((kind, args)) = split_command(command) else {
+            return String::new();
+        };
+        let result = match kind {
+            '?' => Some(format!("S{}", SIGTRAP)),
+            'g' => Some((0..REGISTER_COUNT).map(|i| format_register(read_register(cpu, i))).collect()),
+            'G' => write_registers(cpu, args),
+            'p' => parse_hex(args)
+                .filter(|&i| i < REGISTER_COUNT)
+                .map(|i| format_register(read_register(cpu, i))),
+            'P' => args.split_once('=').and_then(|(index, value)| {
+                let index = parse_hex(index).filter(|&i| i < REGISTER_COUNT)?;
+                write_register(cpu, index, parse_register(value)?);
+                Some("OK".to_string())
+            }),
+            'm' => parse_range(args).map(|(address, length)| {
+                (address..address + length)
+                    .map(|a| format!("{:02x}", cpu.mmu.peek_byte(a & 0xffff)))
+                    .collect()
+            }),
+            'M' => args.split_once(':').and_then(|(range, data)| {
+                let (address, length) = parse_range(range)?;
+                let bytes = parse_bytes(data).filter(|b| b.len() == length)?;
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    cpu.mmu.write_byte((address + i) & 0xffff, byte);
+                }
+                // Don't stop on our own writes
+                cpu.mmu.watchpoints.take_hit();
+                Some("OK".to_string())
+            }),
+            'Z' | 'z' => self.set_breakpoint(cpu, kind == 'Z', args),
+            'q' if args.starts_with("Supported") => Some("PacketSize=1000".to_string()),
+            'q' if args == "Attached" => Some("1".to_string()),
+            'q' if args == "C" => Some("QC1".to_string()),
+            'q' if args == "fThreadInfo" => Some("m1".to_string()),
+            'q' if args == "sThreadInfo" => Some("l".to_string()),
+            'H' | 'T' => Some("OK".to_string()),
+            _ => Some(String::new()),
+        };
+        result.unwrap_or_else(|| "E01".to_string())
+    }
+
+    /// Handles `Z`/`z` packets: `type,addr,kind`.
+    fn set_breakpoint(&mut self, cpu: &mut Cpu, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let address = parse_hex(fields.next()?).filter(|&a| a <= 0xffff)?;
+        let length = parse_hex(fields.next()?)?.clamp(1, 0x10000);
+        let range = |kind| Watchpoint { kind, start: address, end: (address + length - 1).min(0xffff) };
+
+        let watchpoints = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.push(address);
+                } else {
+                    self.breakpoints.retain(|&b| b != address);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => vec![range(WatchKind::Write)],
+            "3" => vec![range(WatchKind::Read)],
+            "4" => vec![range(WatchKind::Read), range(WatchKind::Write)],
+            _ => return Some(String::new()),
+        };
+        for watchpoint in watchpoints {
+            if insert {
+                cpu.mmu.watchpoints.add(watchpoint);
+            } else {
+                cpu.mmu.watchpoints.remove_matching(watchpoint);
+            }
+        }
+        Some("OK".to_string())
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn read_packet(&mut self) -> io::Result<Packet> {
+        loop {
+            match self.read_byte()? {
+                0x03 => return Ok(Packet::Interrupt),
+                b'$' => {}
+                _ => continue,
+            }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let received = [self.read_byte()?, self.read_byte()?];
+            let received = std::str::from_utf8(&received).ok().and_then(parse_hex);
+
+            if received == Some(checksum(&data) as usize) {
+                self.stream.write_all(b"+")?;
+                return Ok(Packet::Command(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            match self.read_byte()? {
+                b'-' => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn stop_reply(hit: Option<WatchHit>) -> String {
+    match hit {
+        None => format!("S{}", SIGTRAP),
+        Some(hit) => {
+            let kind = match hit.watchpoint.kind {
+                WatchKind::Read => "rwatch",
+                WatchKind::Write | WatchKind::Change => "watch",
+            };
+            format!("T{}{}:{:x};", SIGTRAP, kind, hit.address)
+        }
+    }
+}
+
+fn read_register(cpu: &Cpu, index: usize) -> u16 {
+    let r = &cpu.registers;
+    match index {
+        0 => r.get_af(),
+        1 => r.get_bc(),
+        2 => r.get_de(),
+        3 => r.get_hl(),
+        4 => cpu.sp as u16,
+        _ => cpu.debug_pc() as u16,
+    }
+}
+
+fn write_register(cpu: &mut Cpu, index: usize, value: u16) {
+    let r = &mut cpu.registers;
+    match index {
+        0 => r.set_af(value),
+        1 => r.set_bc(value),
+        2 => r.set_de(value),
+        3 => r.set_hl(value),
+        4 => cpu.sp = value as usize,
+        _ => cpu.set_pc(value as usize),
+    }
+}
+
+fn write_registers(cpu: &mut Cpu, data: &str) -> Option<String> {
+    for (index, value) in parse_registers(data)?.into_iter().enumerate() {
+        // Only move PC if it changed, since that refetches the opcode
+        if index != 5 || value as usize != cpu.debug_pc() {
+            write_register(cpu, index, value);
+        }
+    }
+    Some("OK".to_string())
+}
+
+/// Registers are sent as little-endian hex.
+fn format_register(value: u16) -> String {
+    format!("{:02x}{:02x}", value as u8, value >> 8)
+}
+
+/// Parses the payload of a `G` packet, all registers in order.
+fn parse_registers(text: &str) -> Option<Vec<u16>> {
+    let bytes = parse_bytes(text).filter(|b| b.len() == REGISTER_COUNT * 2)?;
+    Some(bytes.chunks(2).map(|pair| pair[0] as u16 | (pair[1] as u16) << 8).collect())
+}
+
+fn parse_register(text: &str) -> Option<u16> {
+    let bytes = parse_bytes(text).filter(|b| b.len() == 2)?;
+    Some((bytes[1] as u16) << 8 | bytes[0] as u16)
+}
+
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Parses `addr,length`, limited to the 64KiB address space.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, length) = text.split_once(',')?;
+    let address = parse_hex(address).filter(|&a| a <= 0xffff)?;
+    Some((address, parse_hex(length)?.min(0x10000)))
+}
+
+/// Splits a packet into its command character and arguments.
+fn split_command(command: &str) -> Option<(char, &str)> {
+    let kind = command.chars().next()?;
+    Some((kind, &command[kind.len_utf8()..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(b"OK"), 0x9a);
+        assert_eq!(checksum(b""), 0);
+    }
+
+    #[test]
+    fn test_register_encoding() {
+        assert_eq!(format_register(0x01b0), "b001");
+        assert_eq!(parse_register("b001"), Some(0x01b0));
+        assert_eq!(parse_register("b0"), None);
+        assert_eq!(parse_range("c000,10"), Some((0xc000, 0x10)));
+        assert_eq!(parse_range("ffff,ffffffff"), Some((0xffff, 0x10000)));
+        assert_eq!(parse_range("10000,1"), None);
+    }
+
+    #[test]
+    fn test_parse_registers() {
+        let text = "b001130000d84d01feff0001";
+        assert_eq!(parse_registers(text), Some(vec![0x01b0, 0x0013, 0xd800, 0x014d, 0xfffe, 0x0100]));
+        assert_eq!(parse_registers(&text[..20]), None);
+        // U+FFFD from a lossy decode, 24 bytes long but not 24 hex digits
+        let text = "b001130000d84d01feff\u{fffd}0";
+        assert_eq!(text.len(), 24);
+        assert_eq!(parse_registers(text), None);
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command(""), None);
+        assert_eq!(split_command("m c000,2"), Some(('m', " c000,2")));
+        assert_eq!(split_command("\u{fffd}x"), Some(('\u{fffd}', "x")));
+    }
+}
@@ -1,4 +1,5 @@
-use super::Joypad;
+use gameboy::frontend::Controls;
+use gameboy::joypad::Joypad;
 use sdl2::EventPump;
 use sdl2::keyboard::{Scancode, Keycode};
 use sdl2::event::{Event, WindowEvent};
@@ -6,25 +7,40 @@ use sdl2::event::{Event, WindowEvent};
 
 pub struct Input {
     event_pump: EventPump,
-    pub quit: bool,
-    pub pause: bool,
-    pub mute: bool,
-    pub run_2x: bool,
+    controls: Controls,
+    slot: u8,
+}
+
+fn slot_for_key(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num0 => Some(0),
+        Keycode::Num1 => Some(1),
+        Keycode::Num2 => Some(2),
+        Keycode::Num3 => Some(3),
+        Keycode::Num4 => Some(4),
+        Keycode::Num5 => Some(5),
+        Keycode::Num6 => Some(6),
+        Keycode::Num7 => Some(7),
+        Keycode::Num8 => Some(8),
+        Keycode::Num9 => Some(9),
+        _ => None,
+    }
 }
 
 impl Input {
     pub fn new(event_pump: EventPump) -> Self {
         Self {
             event_pump,
-            quit: false,
-            pause: false,
-            mute: false,
-            run_2x: false,
+            controls: Controls::default(),
+            slot: 1,
         }
     }
 
-    pub fn poll_inputs(&mut self, joypad: &mut Joypad) {
+    pub fn poll_inputs(&mut self, joypad: &mut Joypad) -> Controls {
         let (mut buttons_state, mut directions_state) = joypad.get_values();
+        self.controls.save_state = None;
+        self.controls.load_state = None;
+        self.controls.debug_break = false;
         for event in self.event_pump.poll_iter() {
             match event {
                 Event::KeyDown { scancode: Some(Scancode::Down),  .. } => directions_state |= 0x08,
@@ -48,24 +64,40 @@ impl Input {
                 Event::KeyUp { scancode: Some(Scancode::W),      .. } => buttons_state &= !0x01,
 
                 Event::KeyDown { keycode: Some(Keycode::P), .. } => {
-                    self.pause = !self.pause;
-                    if self.pause { println!("Paused") } else { println!("Unpaused") };
+                    self.controls.pause = !self.controls.pause;
+                    if self.controls.pause { println!("Paused") } else { println!("Unpaused") };
                 }
                 Event::KeyDown { keycode: Some(Keycode::LShift), .. } => {
-                    self.run_2x = !self.run_2x;
-                    if self.run_2x { println!("Running at double speed") } else { println!("Running at normal speed") };
+                    self.controls.run_2x = !self.controls.run_2x;
+                    if self.controls.run_2x { println!("Running at double speed") } else { println!("Running at normal speed") };
                 }
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => self.controls.rewind = true,
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => self.controls.rewind = false,
                 Event::KeyDown { keycode: Some(Keycode::M), .. } => {
-                    self.mute = !self.mute;
-                    if self.mute { println!("Muted") } else { println!("Unmuted") };
+                    self.controls.mute = !self.controls.mute;
+                    if self.controls.mute { println!("Muted") } else { println!("Unmuted") };
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                    self.controls.disable_cheats = !self.controls.disable_cheats;
+                    if self.controls.disable_cheats { println!("Cheats disabled") } else { println!("Cheats enabled") };
                 }
-                Event::Window { win_event: WindowEvent::Close, .. }  => self.quit = true,
-                Event::Quit { .. } => self.quit = true,
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                    self.controls.debug_break = true;
+                    self.controls.pause = false;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => self.controls.save_state = Some(self.slot),
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => self.controls.load_state = Some(self.slot),
+                Event::KeyDown { keycode: Some(keycode), .. } if slot_for_key(keycode).is_some() => {
+                    self.slot = slot_for_key(keycode).unwrap();
+                    println!("Save state slot {}", self.slot);
+                }
+                Event::Window { win_event: WindowEvent::Close, .. }  => self.controls.quit = true,
+                Event::Quit { .. } => self.controls.quit = true,
                 _ => {}
             }
         }
 
         joypad.set_values(buttons_state, directions_state);
-
+        self.controls
     }
 }
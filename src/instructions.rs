@@ -2,7 +2,7 @@ use super::Cpu;
 use super::InterruptState;
 use super::registers::Flags;
 
-impl<'a> Cpu<'a> {
+impl Cpu {
     pub fn ld_a16_sp(&mut self) {
         match self.cycles {
             0 => {
@@ -1076,10 +1076,14 @@ impl<'a> Cpu<'a> {
         self.cycles = 1;
     }
 
+    /// IME turns on after the next instruction. A second EI is that next
+    /// instruction, so it turns IME on at its end.
     pub fn enable_interrupts(&mut self) {
-        if self.mmu.interrupt_controller.state == InterruptState::Disabled {
-            self.mmu.interrupt_controller.state = InterruptState::Scheduled;
-        }
+        let interrupt_controller = &mut self.mmu.interrupt_controller;
+        interrupt_controller.state = match interrupt_controller.state {
+            InterruptState::Disabled => InterruptState::Scheduled,
+            _ => InterruptState::Enabled,
+        };
         self.cycles = 1;
     }
 
@@ -1284,13 +1288,31 @@ impl<'a> Cpu<'a> {
         self.cycles = 1;
     }
 
+    /// With IME off and an interrupt already pending, HALT doesn't halt and
+    /// triggers the HALT bug instead. HALT re-runs every cycle while halted,
+    /// so this is only decided on the first.
     pub fn halt(&mut self) {
-        self.halt = true;
+        if !self.halt {
+            let interrupt_controller = &self.mmu.interrupt_controller;
+            if interrupt_controller.state != InterruptState::Enabled && interrupt_controller.poll_interrupts() {
+                self.halt_bug = true;
+            } else {
+                self.halt = true;
+            }
+        }
         self.cycles = 1;
     }
 
+    /// Switches speed if one was prepared, otherwise stops the system clock
+    /// until a button is pressed. Either way DIV is reset. With a button
+    /// already held, STOP does nothing.
     pub fn stop(&mut self) {
-        self.mmu.switch_speed();
+        if self.mmu.switch_speed() {
+            self.mmu.timer.write(0xff04, 0);
+        } else if !self.mmu.joypad.any_pressed() {
+            self.mmu.timer.write(0xff04, 0);
+            self.stopped = true;
+        }
         self.cycles = 1;
     }
 }
@@ -1,17 +1,25 @@
+use nanoserde::{DeBin, SerBin};
 
-#[derive(PartialEq)]
+#[derive(PartialEq, SerBin, DeBin)]
 pub enum InterruptState{
     Disabled,
     Scheduled,
     Enabled,
 }
 
+#[derive(SerBin, DeBin)]
 pub struct InterruptController {
     pub state: InterruptState,
     pub interrupt_enable: u8,
     pub interrupt_flag: u8,
 }
 
+impl Default for InterruptController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl InterruptController {
     pub fn new() -> Self {
         InterruptController {
@@ -23,12 +31,7 @@ impl InterruptController {
 
     pub fn write(&mut self, address: usize, value: u8) {
         match address {
-            0xffff => {
-                if self.interrupt_enable & 0x10 == 0 && value & 0x10 != 0 {
-                    println!("joypad interrupt enabled (unimplemented)");
-                }
-                self.interrupt_enable = value;
-            }
+            0xffff => self.interrupt_enable = value,
             0xff0f => self.interrupt_flag = value,
             _ => panic!("Invalid write to Interrupt Controller, address: {:02x}", address),
         }
@@ -43,45 +46,49 @@ impl InterruptController {
     }
 
     pub fn poll_interrupts(&self) -> bool {
-        self.interrupt_enable & self.interrupt_flag != 0
+        self.interrupt_enable & self.interrupt_flag & 0x1f != 0
+    }
+
+    /// Starts dispatching an interrupt if IME is on and one is pending,
+    /// turning IME off.
+    pub fn start_dispatch(&mut self) -> bool {
+        if self.state == InterruptState::Enabled && self.poll_interrupts() {
+            self.state = InterruptState::Disabled;
+            true
+        } else {
+            false
+        }
     }
 
-    pub fn pending_interrupts(&mut self) -> Option<usize> {
-        match self.state {
-            InterruptState::Enabled => {
-                let pending = self.interrupt_enable & self.interrupt_flag;
-                if pending & 0x01 != 0 { // vblank interrupt
-                    self.state = InterruptState::Disabled;
-                    self.interrupt_flag &= !0x01;
-                    Some(0x0040)
-                } else if pending & 0x02 != 0 { // LCD STAT
-                    self.state = InterruptState::Disabled;
-                    self.interrupt_flag &= !0x02;
-                    Some(0x0048)
-                } else if pending & 0x04 != 0 { // Timer
-                    self.state = InterruptState::Disabled;
-                    self.interrupt_flag &= !0x04;
-                    Some(0x0050)
-                } else if pending & 0x08 != 0 { // Serial
-                    self.state = InterruptState::Disabled;
-                    self.interrupt_flag &= !0x08;
-                    Some(0x0058)
-                } else if pending & 0x10 != 0 { // Joypad
-                    self.state = InterruptState::Disabled;
-                    self.interrupt_flag &= !0x10;
-                    Some(0x0060)
-                } else {
-                    None
-                }
-            }
-            InterruptState::Scheduled => {
-                self.state = InterruptState::Enabled;
-                None
-            }
-            InterruptState::Disabled => {
-                None
-            }
+    /// Acknowledges the highest priority pending interrupt and returns its
+    /// vector: VBlank, LCD STAT, timer, serial, then joypad. If none is
+    /// pending any more, the dispatch jumps to 0x0000 instead.
+    pub fn take_interrupt(&mut self) -> usize {
+        let pending = self.interrupt_enable & self.interrupt_flag & 0x1f;
+        if pending == 0 {
+            return 0x0000;
         }
+        let bit = pending.trailing_zeros();
+        self.interrupt_flag &= !(1 << bit);
+        0x0040 + bit as usize * 8
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_interrupt() {
+        let mut interrupts = InterruptController::new();
+        interrupts.interrupt_enable = 0x1c;
+        interrupts.interrupt_flag = 0xe5;
+        assert_eq!(interrupts.take_interrupt(), 0x0050);
+        assert_eq!(interrupts.interrupt_flag, 0xe1);
+
+        // IE changed mid-dispatch so nothing is pending any more
+        assert_eq!(interrupts.take_interrupt(), 0x0000);
+        assert_eq!(interrupts.interrupt_flag, 0xe1);
+    }
+}
@@ -1,9 +1,19 @@
+use nanoserde::{DeBin, SerBin};
 
+#[derive(SerBin, DeBin)]
 pub struct Joypad {
     select_buttons: bool,
     select_directions: bool,
     buttons_state: u8,
     directions_state: u8,
+    /// P10-P13 as last seen by `poll_interrupt`.
+    last_lines: u8,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Joypad {
@@ -13,6 +23,7 @@ impl Joypad {
             select_directions: true,
             buttons_state: 0x00,
             directions_state: 0x00,
+            last_lines: 0x0f,
         }
     }
 
@@ -25,15 +36,31 @@ impl Joypad {
         let mut value = 0xff;
         if self.select_buttons { value &= 0xdf }
         if self.select_directions { value &= 0xef }
+        // With both groups selected, a line reads low if either pulls it low
         if self.select_buttons {
             value &= !self.buttons_state;
-        } else if self.select_directions {
+        }
+        if self.select_directions {
             value &= !self.directions_state;
         }
 
         value
     }
 
+    /// Whether any of P10-P13 went low since the last call, which requests
+    /// the joypad interrupt. Only lines of selected groups can go low.
+    pub fn poll_interrupt(&mut self) -> bool {
+        let lines = self.read() & 0x0f;
+        let falling = self.last_lines & !lines != 0;
+        self.last_lines = lines;
+        falling
+    }
+
+    /// Whether a selected button is held, which wakes the CPU from STOP.
+    pub fn any_pressed(&self) -> bool {
+        self.read() & 0x0f != 0x0f
+    }
+
     pub fn set_values(&mut self, buttons: u8, directions: u8) {
         self.buttons_state = buttons;
         self.directions_state = directions;
@@ -43,3 +70,28 @@ impl Joypad {
         (self.buttons_state, self.directions_state)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_on_selected_lines() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x20); // directions only
+        assert!(!joypad.poll_interrupt());
+
+        joypad.set_values(0x01, 0x00);
+        assert!(!joypad.poll_interrupt());
+        assert!(!joypad.any_pressed());
+
+        joypad.set_values(0x01, 0x02);
+        assert!(joypad.poll_interrupt());
+        assert!(!joypad.poll_interrupt());
+
+        // Selecting buttons too pulls down A's line, which is still held
+        joypad.write(0x00);
+        assert_eq!(joypad.read() & 0x0f, 0x0c);
+        assert!(joypad.poll_interrupt());
+    }
+}
@@ -0,0 +1,55 @@
+#![allow(clippy::verbose_bit_mask)]
+
+pub mod boot;
+pub mod cli;
+pub mod cpu;
+pub use cpu::Cpu;
+pub mod mmu;
+use mmu::Mmu;
+pub mod ppu;
+pub use ppu::Ppu;
+pub mod sound;
+pub use sound::SoundController;
+pub mod interrupts;
+use interrupts::*;
+pub mod timer;
+use timer::Timer;
+pub mod joypad;
+use joypad::Joypad;
+pub mod serial;
+use serial::SerialLink;
+pub mod link;
+pub mod bgb_link;
+pub mod linked;
+pub mod printer;
+pub mod cartridge;
+pub mod cheats;
+pub use cartridge::Cartridge;
+pub mod frontend;
+pub use frontend::Frontend;
+pub mod call_stack;
+pub mod debugger;
+pub mod disassembler;
+mod game_boy;
+pub mod gdb;
+pub use game_boy::GameBoy;
+mod instructions;
+mod mbc;
+pub mod registers;
+pub mod movie;
+pub mod profiler;
+pub mod ram_search;
+pub mod rewind;
+pub mod save_state;
+pub mod screenshot;
+pub mod symbols;
+pub mod trace;
+pub mod watchpoints;
+
+pub const DEBUG: bool = false;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// 4MHz cycles per frame at normal speed (154 lines of 456 cycles).
+pub const CYCLES_PER_FRAME: u32 = 70224;
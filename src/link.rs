@@ -0,0 +1,124 @@
+//! Link cable between two emulator processes over TCP.
+//!
+//! Each message is two bytes, a kind and a data byte. The side clocking a
+//! transfer sends `TRANSFER` with its byte, and the other side answers with
+//! `REPLY` and the byte it shifted out, or 0xff if it wasn't waiting on an
+//! external clock.
+
+use super::interrupts::InterruptController;
+use super::serial::{LinkCable, SerialLink};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+const TRANSFER: u8 = 0x01;
+const REPLY: u8 = 0x02;
+
+/// How long a transfer waits for the other side to answer.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub struct TcpLink {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    /// Transfers clocked by the other side, waiting for `poll`.
+    transfers: VecDeque<u8>,
+    reply: Option<u8>,
+    /// Replies to transfers that already timed out, to be dropped.
+    stale_replies: usize,
+}
+
+impl TcpLink {
+    /// Waits for the other emulator to connect on `127.0.0.1:port`.
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for the link cable on 127.0.0.1:{}", port);
+        let (stream, address) = listener.accept()?;
+        println!("Link cable connected from {}", address);
+        TcpLink::new(stream)
+    }
+
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        println!("Link cable connected to {}", stream.peer_addr()?);
+        TcpLink::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(TcpLink {
+            stream,
+            buffer: vec![],
+            transfers: VecDeque::new(),
+            reply: None,
+            stale_replies: 0,
+        })
+    }
+
+    /// Reads what has arrived, waiting up to `timeout` for at least one byte
+    /// if given.
+    fn read_messages(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_nonblocking(timeout.is_none())?;
+        self.stream.set_read_timeout(timeout)?;
+        let mut bytes = [0; 64];
+        let result = self.stream.read(&mut bytes);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(count) => self.buffer.extend_from_slice(&bytes[..count]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+
+        while self.buffer.len() >= 2 {
+            let (kind, byte) = (self.buffer[0], self.buffer[1]);
+            self.buffer.drain(..2);
+            match kind {
+                TRANSFER => self.transfers.push_back(byte),
+                REPLY if self.stale_replies > 0 => self.stale_replies -= 1,
+                REPLY => self.reply = Some(byte),
+                _ => return Err(io::Error::new(ErrorKind::InvalidData, "unknown link message")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LinkCable for TcpLink {
+    fn send(&mut self, byte: u8, _control: u8, _time: u64) -> io::Result<()> {
+        self.reply = None;
+        self.stream.write_all(&[TRANSFER, byte])
+    }
+
+    fn receive(&mut self) -> io::Result<u8> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while self.reply.is_none() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            self.read_messages(Some(deadline - now))?;
+            // This side is clocking, so it can't also be clocked
+            while self.transfers.pop_front().is_some() {
+                self.stream.write_all(&[REPLY, 0xff])?;
+            }
+        }
+        match self.reply.take() {
+            Some(byte) => Ok(byte),
+            None => {
+                self.stale_replies += 1;
+                Ok(0xff)
+            }
+        }
+    }
+
+    fn poll(&mut self, serial: &mut SerialLink, interrupts: &mut InterruptController, _time: u64) -> io::Result<()> {
+        self.read_messages(None)?;
+        while let Some(byte) = self.transfers.pop_front() {
+            let reply = serial.external_transfer(byte, interrupts).unwrap_or(0xff);
+            self.stream.write_all(&[REPLY, reply])?;
+        }
+        Ok(())
+    }
+}
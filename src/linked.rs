@@ -0,0 +1,200 @@
+//! Two Game Boys in one process with their link ports wired together.
+//!
+//! Both machines run in lockstep, one cycle each in turn, so a linked session
+//! is deterministic. A transfer completes on both sides in the same cycle: the
+//! clocking side gets the other's byte from `receive`, and the other side is
+//! handed the clocking side's byte straight after.
+
+use super::frontend::{AudioSink, Controls, Frontend, InputSource, VideoSink};
+use super::game_boy::GameBoy;
+use super::joypad::Joypad;
+use super::serial::{LinkCable, SerialLink};
+use super::interrupts::InterruptController;
+use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+/// Width of the combined frame, the first Game Boy's screen on the left.
+pub const LINKED_SCREEN_WIDTH: usize = SCREEN_WIDTH * 2;
+
+#[derive(Default)]
+struct Wire {
+    /// Each side's byte while it waits on an external clock.
+    waiting: [Option<u8>; 2],
+    /// The byte each side last started clocking out.
+    sending: [u8; 2],
+    /// Bytes clocked in from the other side, to finish each side's transfer.
+    incoming: [Option<u8>; 2],
+}
+
+struct WireEnd {
+    wire: Rc<RefCell<Wire>>,
+    side: usize,
+}
+
+impl LinkCable for WireEnd {
+    fn send(&mut self, byte: u8, _control: u8, _time: u64) -> io::Result<()> {
+        self.wire.borrow_mut().sending[self.side] = byte;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<u8> {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        match wire.waiting[other].take() {
+            Some(byte) => {
+                wire.incoming[other] = Some(wire.sending[self.side]);
+                Ok(byte)
+            }
+            None => Ok(0xff),
+        }
+    }
+
+    // `LinkedPair` finishes transfers clocked by the other side itself
+    fn poll(&mut self, _serial: &mut SerialLink, _interrupts: &mut InterruptController, _time: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Shows one side's frames in its half of the combined frame. The first Game
+/// Boy owns the frontend's input and audio, and its frames present the
+/// combined frame.
+struct Screen<'a, F> {
+    frontend: &'a mut F,
+    side: usize,
+    frame: &'a mut [u8],
+}
+
+impl<'a, F: Frontend> VideoSink for Screen<'a, F> {
+    fn present_frame(&mut self, frame: &[u8]) {
+        let row_len = SCREEN_WIDTH * 3;
+        for (y, row) in frame.chunks(row_len).enumerate() {
+            let start = y * LINKED_SCREEN_WIDTH * 3 + self.side * row_len;
+            self.frame[start..start + row_len].copy_from_slice(row);
+        }
+        if self.side == 0 {
+            self.frontend.present_frame(self.frame);
+        }
+    }
+}
+
+impl<'a, F: Frontend> AudioSink for Screen<'a, F> {
+    fn audio_ready(&self) -> bool {
+        self.side != 0 || self.frontend.audio_ready()
+    }
+
+    fn queue_audio(&mut self, samples: &[f32]) {
+        if self.side == 0 {
+            self.frontend.queue_audio(samples);
+        }
+    }
+}
+
+impl<'a, F: Frontend> InputSource for Screen<'a, F> {
+    fn poll_inputs(&mut self, joypad: &mut Joypad) -> Controls {
+        if self.side == 0 {
+            self.frontend.poll_inputs(joypad)
+        } else {
+            Controls::default()
+        }
+    }
+}
+
+impl<'a, F: Frontend> Frontend for Screen<'a, F> {
+    fn limit_speed(&self) -> bool {
+        self.frontend.limit_speed()
+    }
+}
+
+/// Runs two Game Boys linked by a cable, showing both screens side by side.
+pub struct LinkedPair {
+    wire: Rc<RefCell<Wire>>,
+    frame: Vec<u8>,
+}
+
+impl LinkedPair {
+    /// Plugs the cable into both Game Boys.
+    pub fn connect(left: &mut GameBoy, right: &mut GameBoy) -> Self {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        left.connect_link(Box::new(WireEnd { wire: wire.clone(), side: 0 }));
+        right.connect_link(Box::new(WireEnd { wire: wire.clone(), side: 1 }));
+        LinkedPair { wire, frame: vec![0; LINKED_SCREEN_WIDTH * SCREEN_HEIGHT * 3] }
+    }
+
+    /// The most recent combined frame, `LINKED_SCREEN_WIDTH` pixels wide.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Runs both Game Boys for one 4MHz cycle. One in double speed runs two
+    /// of its own cycles, so both stay in step in real time.
+    pub fn step<F: Frontend>(&mut self, game_boys: [&mut GameBoy; 2], frontend: &mut F) {
+        for side in 0..2 {
+            let other = 1 - side;
+            let cycles = if game_boys[side].cpu.mmu.double_speed { 2 } else { 1 };
+            for _ in 0..cycles {
+                let mut screen = Screen { frontend: &mut *frontend, side, frame: &mut self.frame };
+                game_boys[side].step(&mut screen);
+
+                let mut wire = self.wire.borrow_mut();
+                wire.waiting[side] = game_boys[side].cpu.mmu.serial_link.external_byte();
+                // Finish the other side's transfer if this side just clocked it
+                if let Some(byte) = wire.incoming[other].take() {
+                    let mmu = &mut game_boys[other].cpu.mmu;
+                    mmu.serial_link.external_transfer(byte, &mut mmu.interrupt_controller);
+                    wire.waiting[other] = None;
+                }
+            }
+        }
+        // Only the first Game Boy is heard
+        while game_boys[1].cpu.mmu.sound_controller.take_audio().is_some() {}
+    }
+
+    /// Runs until either Game Boy asks to quit, then saves both cartridges.
+    /// The first Game Boy takes the frontend's input and plays its audio.
+    pub fn run<F: Frontend>(&mut self, game_boys: [&mut GameBoy; 2], frontend: &mut F) {
+        let [left, right] = game_boys;
+        let limit_speed = frontend.limit_speed();
+        while !left.quit_requested() && !right.quit_requested() {
+            if left.paused() {
+                left.poll_inputs(frontend);
+                thread::sleep(Duration::from_millis(10));
+            } else if left.cpu.mmu.sound_controller.buffer_full() {
+                if !limit_speed || frontend.audio_ready() {
+                    if let Some(samples) = left.cpu.mmu.sound_controller.take_audio() {
+                        frontend.queue_audio(samples);
+                    }
+                } else {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            } else {
+                self.step([&mut *left, &mut *right], frontend);
+            }
+        }
+
+        left.cpu.mmu.cartridge.save();
+        right.cpu.mmu.cartridge.save();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wire_transfer() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let mut left = WireEnd { wire: wire.clone(), side: 0 };
+        left.send(0x42, 0x81, 0).unwrap();
+        assert_eq!(left.receive().unwrap(), 0xff);
+        assert_eq!(wire.borrow().incoming, [None, None]);
+
+        wire.borrow_mut().waiting[1] = Some(0x99);
+        left.send(0x42, 0x81, 0).unwrap();
+        assert_eq!(left.receive().unwrap(), 0x99);
+        assert_eq!(wire.borrow().incoming, [None, Some(0x42)]);
+    }
+}
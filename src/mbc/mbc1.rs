@@ -2,6 +2,7 @@ use super::Mbc;
 use std::path::Path;
 use std::io::{BufWriter, Write};
 use std::fs::File;
+use nanoserde::{DeBin, DeBinErr, SerBin};
 
 pub struct Mbc1 {
     rom: Vec<u8>,
@@ -37,21 +38,23 @@ impl Mbc1 {
 }
 
 impl Mbc for Mbc1 {
-    fn read(&self, address: usize) -> u8 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn rom_bank(&self, address: usize) -> usize {
         if address < 0x4000 {
-            if self.mode && self.rom_size > 4 {
-                let rom_address = (self.bank2 << 5) * 0x4000 + address;
-                self.rom[rom_address]
-            } else {
-                self.rom[address]
-            }
-        } else if address < 0x8000 {
-            let rom_bank = if self.rom_size > 4 {
-                ((self.bank2 << 5) + self.bank1) % (2 << self.rom_size)
-            } else {
-                self.bank1 % (2 << self.rom_size)
-            };
-            let rom_address = rom_bank * 0x4000 + (address - 0x4000);
+            if self.mode && self.rom_size > 4 { self.bank2 << 5 } else { 0 }
+        } else if self.rom_size > 4 {
+            ((self.bank2 << 5) + self.bank1) % (2 << self.rom_size)
+        } else {
+            self.bank1 % (2 << self.rom_size)
+        }
+    }
+
+    fn read(&self, address: usize) -> u8 {
+        if address < 0x8000 {
+            let rom_address = self.rom_bank(address) * 0x4000 + (address & 0x3fff);
             self.rom[rom_address]
         } else if (0xa000..0xc000).contains(&address) {
             if self.ram_enabled {
@@ -100,6 +103,23 @@ impl Mbc for Mbc1 {
             buffer.write_all(&self.ram).expect("Failed to save");
         }
     }
+
+    fn save_state(&self, output: &mut Vec<u8>) {
+        self.ram.ser_bin(output);
+        self.ram_enabled.ser_bin(output);
+        self.bank1.ser_bin(output);
+        self.bank2.ser_bin(output);
+        self.mode.ser_bin(output);
+    }
+
+    fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.ram = DeBin::de_bin(offset, data)?;
+        self.ram_enabled = DeBin::de_bin(offset, data)?;
+        self.bank1 = DeBin::de_bin(offset, data)?;
+        self.bank2 = DeBin::de_bin(offset, data)?;
+        self.mode = DeBin::de_bin(offset, data)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -2,6 +2,7 @@ use super::Mbc;
 use std::path::Path;
 use std::io::{BufWriter, Write};
 use std::fs::File;
+use nanoserde::{DeBin, DeBinErr, SerBin};
 
 pub struct Mbc2 {
     rom: Vec<u8>,
@@ -35,12 +36,19 @@ impl Mbc2 {
 }
 
 impl Mbc for Mbc2 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn rom_bank(&self, address: usize) -> usize {
+        if address < 0x4000 { 0 } else { (self.rom_bank % (2 << self.rom_size)) as usize }
+    }
+
     fn read(&self, address: usize) -> u8 {
         if address < 0x4000 {
             self.rom[address]
         } else if address < 0x8000 {
-            let rom_bank = self.rom_bank % (2 << self.rom_size);
-            let rom_address = rom_bank as usize * 0x4000 + (address - 0x4000);
+            let rom_address = self.rom_bank(address) * 0x4000 + (address - 0x4000);
             self.rom[rom_address]
         } else if (0xa000..0xc000).contains(&address) {
             if self.ram_enabled {
@@ -79,4 +87,17 @@ impl Mbc for Mbc2 {
             buffer.write_all(&self.ram).expect("Failed to save");
         }
     }
+
+    fn save_state(&self, output: &mut Vec<u8>) {
+        self.ram.ser_bin(output);
+        self.ram_enabled.ser_bin(output);
+        self.rom_bank.ser_bin(output);
+    }
+
+    fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.ram = DeBin::de_bin(offset, data)?;
+        self.ram_enabled = DeBin::de_bin(offset, data)?;
+        self.rom_bank = DeBin::de_bin(offset, data)?;
+        Ok(())
+    }
 }
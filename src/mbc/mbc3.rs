@@ -2,8 +2,7 @@ use super::Mbc;
 use std::path::Path;
 use std::io::{BufWriter, Write};
 use std::fs::File;
-use std::time::{SystemTime, Duration};
-use nanoserde::{DeBin, SerBin};
+use nanoserde::{DeBin, DeBinErr, SerBin};
 
 #[derive(Clone, Default, SerBin, DeBin)]
 struct RtcTime {
@@ -34,6 +33,9 @@ pub struct Mbc3 {
     rtc: Rtc,
     has_battery: bool,
     has_rtc: bool,
+    /// Unix time in microseconds that the RTC was last synced to, advanced
+    /// with emulated time. `None` until the first `sync_rtc`.
+    clock: Option<u64>,
 }
 
 impl Mbc3 {
@@ -48,6 +50,7 @@ impl Mbc3 {
             rtc: Rtc::default(),
             has_battery: battery,
             has_rtc: rtc,
+            clock: None,
         };
 
         mbc.rom[0..rom.len()].copy_from_slice(&rom);
@@ -63,29 +66,71 @@ impl Mbc3 {
             if rtc {
                 mbc.rtc = DeBin::deserialize_bin(&load_data[load_index..load_index+22]).unwrap();
                 load_index += 22;
-                let last_save_millis: u64 = DeBin::deserialize_bin(&load_data[load_index..load_index+8]).unwrap();
-                let last_save = SystemTime::UNIX_EPOCH + Duration::from_millis(last_save_millis);
-                let duration_since_last_save = SystemTime::now().duration_since(last_save);
-                let micros_since_last_save = match duration_since_last_save {
-                    Ok(duration) => duration.as_micros() as u64,
-                    Err(_) => 0,
-                };
-                mbc.update_rtc(micros_since_last_save);
+                if load_data.len() >= load_index + 8 {
+                    let clock_millis: u64 = DeBin::deserialize_bin(&load_data[load_index..load_index+8]).unwrap();
+                    mbc.clock = Some(clock_millis * 1000);
+                }
             }
         }
 
         mbc
     }
 
+    fn advance_rtc(&mut self, micros: u64) {
+        if !self.rtc.halt {
+            let new_micros = self.rtc.live_time.micros as u64 + micros;
+            self.rtc.live_time.micros = (new_micros % 1_000_000) as u32;
+            let seconds_cin = new_micros / 1_000_000;
+
+            let minutes_cin = if seconds_cin > 0 {
+                let new_seconds = if self.rtc.live_time.seconds > 59 { 59 } else { self.rtc.live_time.seconds } as u64 + seconds_cin;
+                self.rtc.live_time.seconds = (new_seconds % 60) as u8;
+                new_seconds / 60
+            } else {
+                0
+            };
+
+            let hours_cin = if minutes_cin > 0 {
+                let new_minutes = if self.rtc.live_time.minutes > 59 { 59 } else { self.rtc.live_time.minutes } as u64 + minutes_cin;
+                self.rtc.live_time.minutes = (new_minutes % 60) as u8;
+                new_minutes / 60
+            } else {
+                0
+            };
+
+            let days_cin = if hours_cin > 0 {
+                let new_hours = if self.rtc.live_time.hours > 23 { 23 } else { self.rtc.live_time.hours } as u64 + hours_cin;
+                self.rtc.live_time.hours = (new_hours % 24) as u8;
+                new_hours / 24
+            } else {
+                0
+            };
+
+            if days_cin > 0 {
+                let new_days = self.rtc.live_time.days as u64 + days_cin;
+                self.rtc.live_time.days = (new_days % 512) as u16;
+                if new_days > 511 {
+                    self.rtc.live_time.day_carry = true;
+                }
+            }
+        }
+    }
 }
 
 impl Mbc for Mbc3 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn rom_bank(&self, address: usize) -> usize {
+        if address < 0x4000 { 0 } else { self.rom_bank % (2 << self.rom_size) }
+    }
+
     fn read(&self, address: usize) -> u8 {
         if address < 0x4000 {
             self.rom[address]
         } else if address < 0x8000 {
-            let rom_bank = self.rom_bank % (2 << self.rom_size);
-            let rom_address = rom_bank * 0x4000 + (address - 0x4000);
+            let rom_address = self.rom_bank(address) * 0x4000 + (address - 0x4000);
             self.rom[rom_address]
         } else if (0xa000..0xc000).contains(&address) {
             if self.ram_timer_select < 4 && self.ram_timer_enabled {
@@ -168,49 +213,64 @@ impl Mbc for Mbc3 {
         }
         if self.has_rtc {
             buffer.write_all(&SerBin::serialize_bin(&self.rtc)).expect("Failed to save");
-            let now_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
-            let now_millis = now_time.as_millis() as u64;
-            buffer.write_all(&SerBin::serialize_bin(&now_millis)).expect("Failed to save");
+            if let Some(clock) = self.clock {
+                let clock_millis = clock / 1000;
+                buffer.write_all(&SerBin::serialize_bin(&clock_millis)).expect("Failed to save");
+            }
         }
     }
-    
-    fn update_rtc(&mut self, micros: u64) {
-        if !self.rtc.halt {
-            let new_micros = self.rtc.live_time.micros as u64 + micros;
-            self.rtc.live_time.micros = (new_micros % 1_000_000) as u32;
-            let seconds_cin = new_micros / 1_000_000;
 
-            let minutes_cin = if seconds_cin > 0 {
-                let new_seconds = if self.rtc.live_time.seconds > 59 { 59 } else { self.rtc.live_time.seconds } as u64 + seconds_cin;
-                self.rtc.live_time.seconds = (new_seconds % 60) as u8;
-                new_seconds / 60
-            } else {
-                0
-            };
+    fn save_state(&self, output: &mut Vec<u8>) {
+        self.ram.ser_bin(output);
+        self.rom_bank.ser_bin(output);
+        self.ram_timer_enabled.ser_bin(output);
+        self.ram_timer_select.ser_bin(output);
+        self.rtc.ser_bin(output);
+        self.clock.ser_bin(output);
+    }
 
-            let hours_cin = if minutes_cin > 0 {
-                let new_minutes = if self.rtc.live_time.minutes > 59 { 59 } else { self.rtc.live_time.minutes } as u64 + minutes_cin;
-                self.rtc.live_time.minutes = (new_minutes % 60) as u8;
-                new_minutes / 60
-            } else {
-                0
-            };
+    fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.ram = DeBin::de_bin(offset, data)?;
+        self.rom_bank = DeBin::de_bin(offset, data)?;
+        self.ram_timer_enabled = DeBin::de_bin(offset, data)?;
+        self.ram_timer_select = DeBin::de_bin(offset, data)?;
+        self.rtc = DeBin::de_bin(offset, data)?;
+        self.clock = DeBin::de_bin(offset, data)?;
+        Ok(())
+    }
 
-            let days_cin = if hours_cin > 0 {
-                let new_hours = if self.rtc.live_time.hours > 23 { 23 } else { self.rtc.live_time.hours } as u64 + hours_cin;
-                self.rtc.live_time.hours = (new_hours % 24) as u8;
-                new_hours / 24
-            } else {
-                0
-            };
+    fn update_rtc(&mut self, micros: u64) {
+        self.advance_rtc(micros);
+        if let Some(clock) = self.clock.as_mut() {
+            *clock += micros;
+        }
+    }
 
-            if days_cin > 0 {
-                let new_days = self.rtc.live_time.days as u64 + days_cin;
-                self.rtc.live_time.days = (new_days % 512) as u16;
-                if new_days > 511 {
-                    self.rtc.live_time.day_carry = true;
-                }
-            }
+    fn sync_rtc(&mut self, unix_micros: u64) {
+        if let Some(clock) = self.clock {
+            self.advance_rtc(unix_micros.saturating_sub(clock));
         }
+        self.clock = Some(unix_micros);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rtc_follows_emulated_time_until_synced() {
+        let mut mbc = Mbc3::new(vec![0; 0x8000], &[], false, true);
+        mbc.write(0x0000, 0x0a);
+        mbc.write(0x4000, 0x08);
+
+        mbc.update_rtc(2_000_000);
+        assert_eq!(mbc.read(0xa000), 2);
+        mbc.sync_rtc(10_000_000);
+        assert_eq!(mbc.read(0xa000), 2);
+
+        mbc.update_rtc(1_000_000);
+        mbc.sync_rtc(15_000_000);
+        assert_eq!(mbc.read(0xa000), 7);
     }
 }
@@ -2,6 +2,7 @@ use super::Mbc;
 use std::path::Path;
 use std::io::{BufWriter, Write};
 use std::fs::File;
+use nanoserde::{DeBin, DeBinErr, SerBin};
 
 pub struct Mbc5 {
     rom: Vec<u8>,
@@ -35,6 +36,18 @@ impl Mbc5 {
 }
 
 impl Mbc for Mbc5 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn rom_bank(&self, address: usize) -> usize {
+        if address < 0x4000 {
+            0
+        } else {
+            ((self.rom_bank_h as usize) << 8 | self.rom_bank_l as usize) % (2 << self.rom_size as usize)
+        }
+    }
+
     fn write(&mut self, address: usize, value: u8) {
         match address {
             0x0000..=0x1fff => self.ram_enabled = value == 0x0a,
@@ -56,8 +69,7 @@ impl Mbc for Mbc5 {
         match address {
             0x0000..=0x3fff => self.rom[address],
             0x4000..=0x7fff => {
-                let rom_bank = ((self.rom_bank_h as usize) << 8 | self.rom_bank_l as usize) % (2 << self.rom_size as usize);
-                let rom_address = (rom_bank * 0x4000) + address - 0x4000;
+                let rom_address = (self.rom_bank(address) * 0x4000) + address - 0x4000;
                 self.rom[rom_address]
             }
             0xa000..=0xbfff => {
@@ -78,6 +90,23 @@ impl Mbc for Mbc5 {
             buffer.write_all(&self.ram).expect("Failed to save");
         }
     }
+
+    fn save_state(&self, output: &mut Vec<u8>) {
+        self.ram.ser_bin(output);
+        self.ram_enabled.ser_bin(output);
+        self.rom_bank_l.ser_bin(output);
+        self.rom_bank_h.ser_bin(output);
+        self.ram_bank.ser_bin(output);
+    }
+
+    fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.ram = DeBin::de_bin(offset, data)?;
+        self.ram_enabled = DeBin::de_bin(offset, data)?;
+        self.rom_bank_l = DeBin::de_bin(offset, data)?;
+        self.rom_bank_h = DeBin::de_bin(offset, data)?;
+        self.ram_bank = DeBin::de_bin(offset, data)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
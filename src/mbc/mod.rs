@@ -1,3 +1,4 @@
+use nanoserde::DeBinErr;
 use std::path::Path;
 
 mod no_mbc;
@@ -17,9 +18,28 @@ pub trait Mbc {
     
     fn read(&self, address: usize) -> u8;
 
+    /// ROM bank mapped at `address`, which must be below 0x8000.
+    fn rom_bank(&self, address: usize) -> usize {
+        if address < 0x4000 { 0 } else { 1 }
+    }
+
+    /// All cartridge RAM banks.
+    fn ram(&self) -> &[u8];
+
     fn update_rtc(&mut self, _micros: u64) {
         // Default is no RTC
     }
 
+    /// Advances the RTC by the real time that passed since it was last
+    /// synced, e.g. while the emulator was closed.
+    fn sync_rtc(&mut self, _unix_micros: u64) {
+        // Default is no RTC
+    }
+
     fn save(&self, path: &Path);
+
+    /// Appends banking registers and RAM (but not ROM) to a save state.
+    fn save_state(&self, output: &mut Vec<u8>);
+
+    fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr>;
 }
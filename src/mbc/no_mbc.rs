@@ -1,4 +1,5 @@
 use super::Mbc;
+use nanoserde::{DeBin, DeBinErr, SerBin};
 use std::path::Path;
 
 pub struct NoMbc {
@@ -20,6 +21,10 @@ impl NoMbc {
 }
 
 impl Mbc for NoMbc {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
     fn read(&self, address: usize) -> u8 {
         if address < 0x8000 {
             self.rom[address]
@@ -42,5 +47,14 @@ impl Mbc for NoMbc {
 
     fn save(&self, _path: &Path) {
     }
+
+    fn save_state(&self, output: &mut Vec<u8>) {
+        self.ram.ser_bin(output);
+    }
+
+    fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.ram = DeBin::de_bin(offset, data)?;
+        Ok(())
+    }
 }
 
@@ -1,45 +1,44 @@
 use super::interrupts::*;
-use super::{Cartridge, Display, Joypad, SerialLink, SoundController, Timer, DEBUG, Ppu};
-use sdl2::render::TextureCreator;
-use sdl2::video::WindowContext;
+use super::watchpoints::Watchpoints;
+use super::cartridge::crc32;
+use super::{Cartridge, Joypad, SerialLink, SoundController, Timer, DEBUG, Ppu};
 use std::path::Path;
+use nanoserde::{DeBin, DeBinErr, SerBin};
 
-const BOOT_ROM_SIZE: usize = 0x900;
+pub const BOOT_ROM_SIZE: usize = 0x900;
 const BOOT_ROM_SIZE_MINUS_1: usize = BOOT_ROM_SIZE - 1;
 
-pub struct Mmu<'a> {
+pub struct Mmu {
     boot_rom: [u8; BOOT_ROM_SIZE],
     pub cartridge: Cartridge,
     wram: [u8; 0x8000],
     hram: [u8; 0x7f],
     disable_boot_rom: bool,
-    pub ppu: Ppu<'a>,
+    /// CRC-32 of the boot ROM passed to `new`, if any. Not saved in states.
+    boot_rom_crc32: Option<u32>,
+    pub ppu: Ppu,
     pub sound_controller: SoundController,
     pub interrupt_controller: super::InterruptController,
     pub timer: Timer,
     pub joypad: Joypad,
-    serial_link: SerialLink,
+    pub serial_link: SerialLink,
     pub dma_config: DmaConfig,
     wram_bank_sel: u8,
-    dmg_mode: bool,
+    pub dmg_mode: bool,
     pub double_speed: bool,
     prepare_speed_switch: bool,
+    pub watchpoints: Watchpoints,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Default, PartialEq, SerBin, DeBin)]
 pub enum DmaType {
+    #[default]
     Oam,
     GeneralPurpose,
     Hblank,
 }
 
-impl Default for DmaType {
-    fn default() -> Self {
-        DmaType::Oam
-    }
-}
-
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, SerBin, DeBin)]
 pub struct DmaConfig {
     oam_source_address: usize,
     gp_source_address: usize,
@@ -61,22 +60,13 @@ impl DmaConfig {
     }
 }
 
-impl<'a> Mmu<'a> {
-    pub fn new<P: AsRef<Path>>(
-        rom_path: P,
-        sdl: &sdl2::Sdl,
-        display: Display,
-        texture_creator: &'a TextureCreator<WindowContext>,
-        dmg_mode: bool,
-    ) -> Self {
+impl Mmu {
+    /// Without a boot ROM the cartridge is mapped from the start.
+    pub fn new<P: AsRef<Path>>(rom_path: P, dmg_mode: bool, boot_rom_data: Option<Vec<u8>>) -> Self {
         let mut boot_rom = [0; BOOT_ROM_SIZE];
-        let boot_rom_filename = if dmg_mode {
-            "./DMG_ROM.bin"
-        } else {
-            "./cgb_bios.bin"
-        };
-        for (i, byte) in std::fs::read(boot_rom_filename).unwrap().iter().enumerate() {
-            boot_rom[i] = *byte;
+        if let Some(data) = &boot_rom_data {
+            assert!(data.len() <= BOOT_ROM_SIZE, "Boot ROM is larger than {} bytes", BOOT_ROM_SIZE);
+            boot_rom[..data.len()].copy_from_slice(data);
         }
 
         Mmu {
@@ -84,18 +74,20 @@ impl<'a> Mmu<'a> {
             cartridge: Cartridge::new(rom_path),
             wram: [0; 0x8000],
             hram: [0; 0x7f],
-            disable_boot_rom: false,
-            ppu: Ppu::new(display, texture_creator, dmg_mode),
-            sound_controller: SoundController::new(sdl),
+            disable_boot_rom: boot_rom_data.is_none(),
+            boot_rom_crc32: boot_rom_data.as_deref().map(crc32),
+            ppu: Ppu::new(dmg_mode),
+            sound_controller: SoundController::new(),
             interrupt_controller: InterruptController::new(),
             timer: Timer::new(),
             joypad: Joypad::new(),
-            serial_link: SerialLink::new(),
+            serial_link: SerialLink::new(!dmg_mode),
             dma_config: DmaConfig::default(),
             wram_bank_sel: 0,
             dmg_mode,
             double_speed: false,
             prepare_speed_switch: false,
+            watchpoints: Watchpoints::default(),
         }
     }
 
@@ -120,6 +112,10 @@ impl<'a> Mmu<'a> {
                         0xfe00 + dest_address
                     )
                 };
+                if !self.watchpoints.is_empty() {
+                    let old_value = self.ppu.sprite_attribute_table[dest_address];
+                    self.watchpoints.check_write(0xfe00 + dest_address, old_value, value, value);
+                }
                 self.ppu.sprite_attribute_table[dest_address] = value;
                 self.dma_config.count -= 1;
                 self.dma_config.active = true;
@@ -189,7 +185,21 @@ impl<'a> Mmu<'a> {
     }
 
     pub fn read_byte(&self, address: usize) -> u8 {
-        let result = match address {
+        let result = self.peek_byte(address);
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check_read(address, result);
+        }
+
+        if DEBUG {
+            println!("Mmu Read: Address: {:04x}, Data: {:02x}", address, result);
+        }
+
+        result
+    }
+
+    /// Reads without triggering watchpoints, for debugging tools.
+    pub fn peek_byte(&self, address: usize) -> u8 {
+        match address {
             0x100..=0x1ff => self.cartridge.read(address),
             0x0000..=BOOT_ROM_SIZE_MINUS_1 if !self.disable_boot_rom => self.boot_rom[address],
             0x0000..=0x7fff | 0xa000..=0xbfff => self.cartridge.read(address),
@@ -261,13 +271,7 @@ impl<'a> Mmu<'a> {
                 println!("Unmapped read from address {:04x}", address);
                 0xff
             }
-        };
-
-        if DEBUG {
-            println!("Mmu Read: Address: {:04x}, Data: {:02x}", address, result);
         }
-
-        result
     }
 
     pub fn write_byte(&mut self, address: usize, value: u8) {
@@ -275,6 +279,40 @@ impl<'a> Mmu<'a> {
             println!("Mmu Write: Address: {:04x}, Data: {:02x}", address, value);
         }
 
+        if self.watchpoints.is_empty() {
+            self.store_byte(address, value);
+        } else {
+            let old_value = self.peek_byte(address);
+            self.store_byte(address, value);
+            self.watchpoints.check_write(address, old_value, value, self.peek_byte(address));
+        }
+    }
+
+    /// All WRAM banks present in the current mode.
+    pub fn wram(&self) -> &[u8] {
+        if self.dmg_mode { &self.wram[..0x2000] } else { &self.wram }
+    }
+
+    pub fn hram(&self) -> &[u8] {
+        &self.hram
+    }
+
+    /// Makes this frame's GameShark writes. They bypass watchpoints, like
+    /// DMA, since the CPU didn't make them.
+    pub fn apply_cheats(&mut self) {
+        for (bank, address, value) in self.cartridge.cheats.ram_writes() {
+            match (bank, address) {
+                (_, 0x0000..=0x7fff) => {}
+                (Some(bank), 0xd000..=0xdfff) => {
+                    let wram_bank = bank.max(1) as usize;
+                    self.wram[wram_bank * 0x1000 + address - 0xd000] = value;
+                }
+                _ => self.store_byte(address, value),
+            }
+        }
+    }
+
+    fn store_byte(&mut self, address: usize, value: u8) {
         match address {
             0x0000..=0x7fff | 0xa000..=0xbfff => self.cartridge.write(address, value),
             0x8000..=0x9fff => self.ppu.write_vram(address, value),
@@ -380,11 +418,67 @@ impl<'a> Mmu<'a> {
         }
     }
 
-    pub fn switch_speed(&mut self) {
+    /// Switches speed if KEY1 asked for it. Returns whether it did.
+    pub fn switch_speed(&mut self) -> bool {
         if self.prepare_speed_switch {
             self.double_speed = !self.double_speed;
             self.prepare_speed_switch = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// CRC-32 of the boot ROM while it is still mapped, i.e. the boot ROM
+    /// the machine can't run on without.
+    pub fn mapped_boot_rom(&self) -> Option<u32> {
+        self.boot_rom_crc32.filter(|_| !self.disable_boot_rom)
+    }
+
+    /// CRC-32 of the boot ROM this machine was started with, if any.
+    pub fn boot_rom_crc32(&self) -> Option<u32> {
+        self.boot_rom_crc32
+    }
+
+    pub fn save_state(&self, output: &mut Vec<u8>) {
+        self.cartridge.save_state(output);
+        self.wram.ser_bin(output);
+        self.hram.ser_bin(output);
+        self.disable_boot_rom.ser_bin(output);
+        self.ppu.ser_bin(output);
+        self.sound_controller.ser_bin(output);
+        self.interrupt_controller.ser_bin(output);
+        self.timer.ser_bin(output);
+        self.joypad.ser_bin(output);
+        self.serial_link.ser_bin(output);
+        self.dma_config.ser_bin(output);
+        self.wram_bank_sel.ser_bin(output);
+        self.dmg_mode.ser_bin(output);
+        self.double_speed.ser_bin(output);
+        self.prepare_speed_switch.ser_bin(output);
+    }
+
+    pub fn load_state(&mut self, offset: &mut usize, data: &[u8]) -> Result<(), DeBinErr> {
+        self.cartridge.load_state(offset, data)?;
+        self.wram = DeBin::de_bin(offset, data)?;
+        self.hram = DeBin::de_bin(offset, data)?;
+        self.disable_boot_rom = DeBin::de_bin(offset, data)?;
+        self.ppu = DeBin::de_bin(offset, data)?;
+        self.sound_controller = DeBin::de_bin(offset, data)?;
+        self.interrupt_controller = DeBin::de_bin(offset, data)?;
+        self.timer = DeBin::de_bin(offset, data)?;
+        self.joypad = DeBin::de_bin(offset, data)?;
+        let capturing = self.serial_link.capturing_output();
+        self.serial_link = DeBin::de_bin(offset, data)?;
+        if capturing {
+            self.serial_link.capture_output();
         }
+        self.dma_config = DeBin::de_bin(offset, data)?;
+        self.wram_bank_sel = DeBin::de_bin(offset, data)?;
+        self.dmg_mode = DeBin::de_bin(offset, data)?;
+        self.double_speed = DeBin::de_bin(offset, data)?;
+        self.prepare_speed_switch = DeBin::de_bin(offset, data)?;
+        Ok(())
     }
 
     #[allow(dead_code)]
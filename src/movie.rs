@@ -0,0 +1,201 @@
+use super::cheats::Cheats;
+use super::save_state::SaveStateError;
+use super::{Joypad, CYCLES_PER_FRAME};
+use nanoserde::{DeBin, DeBinErr, SerBin};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+const MAGIC: [u8; 8] = *b"GBMOVIE\0";
+
+/// Bump whenever the movie layout changes.
+pub const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    NotAMovie,
+    UnsupportedVersion(u32),
+    WrongRom,
+    Corrupt(String),
+    State(SaveStateError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(e) => write!(f, "{}", e),
+            MovieError::NotAMovie => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion(version) => write!(
+                f,
+                "movie version {} is not supported (expected version {})",
+                version, VERSION
+            ),
+            MovieError::WrongRom => write!(f, "movie was recorded with a different ROM"),
+            MovieError::Corrupt(e) => write!(f, "movie is corrupt: {}", e),
+            MovieError::State(e) => write!(f, "movie start state: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> Self {
+        MovieError::Io(e)
+    }
+}
+
+impl From<DeBinErr> for MovieError {
+    fn from(e: DeBinErr) -> Self {
+        MovieError::Corrupt(e.to_string())
+    }
+}
+
+impl From<SaveStateError> for MovieError {
+    fn from(e: SaveStateError) -> Self {
+        MovieError::State(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, SerBin, DeBin)]
+pub enum MovieStart {
+    PowerOn,
+    SaveState,
+}
+
+/// Joypad input for every frame of a run, plus everything needed to replay it.
+///
+/// A frame here is `CYCLES_PER_FRAME` emulator cycles regardless of LCD or
+/// speed mode, so input lands on the same cycle on every playback. The start
+/// state is always embedded, even for power-on movies, so that cartridge RAM
+/// and the RTC don't depend on the save file of whoever plays it back.
+#[derive(SerBin, DeBin)]
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub dmg_mode: bool,
+    /// Cheat codes enabled while recording, applied again on playback.
+    pub cheats: Vec<String>,
+    pub start: MovieStart,
+    pub state: Vec<u8>,
+    /// `(buttons, directions)` as passed to `Joypad::set_values`.
+    pub inputs: Vec<(u8, u8)>,
+}
+
+impl Movie {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut output = MAGIC.to_vec();
+        VERSION.ser_bin(&mut output);
+        self.ser_bin(&mut output);
+        std::fs::write(path, output)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MovieError> {
+        let data = std::fs::read(path)?;
+        if data.len() < MAGIC.len() || data[0..MAGIC.len()] != MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        let mut offset = MAGIC.len();
+
+        let version: u32 = DeBin::de_bin(&mut offset, &data)?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        Ok(DeBin::de_bin(&mut offset, &data)?)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+}
+
+/// A movie being recorded or played back.
+pub struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    /// Receives frontend input; only copied to the real joypad on frame boundaries.
+    pub input: Joypad,
+    /// The user's cheats while the movie's own are in place for playback.
+    pub cheats: Option<Cheats>,
+    frame: usize,
+    cycles: u32,
+    finished: bool,
+}
+
+impl MovieSession {
+    pub fn new(movie: Movie, mode: MovieMode) -> Self {
+        MovieSession {
+            movie,
+            mode,
+            input: Joypad::new(),
+            cheats: None,
+            frame: 0,
+            cycles: 0,
+            finished: false,
+        }
+    }
+
+    /// Call once per emulator cycle. Returns the input to apply at the start of each frame.
+    pub fn tick(&mut self) -> Option<(u8, u8)> {
+        let frame_start = self.cycles == 0;
+        self.cycles = (self.cycles + 1) % CYCLES_PER_FRAME;
+        if !frame_start {
+            return None;
+        }
+
+        match self.mode {
+            MovieMode::Recording => {
+                let values = self.input.get_values();
+                self.movie.inputs.push(values);
+                Some(values)
+            }
+            MovieMode::Playing => {
+                let values = self.movie.inputs.get(self.frame).copied();
+                self.frame += 1;
+                self.finished = values.is_none();
+                values
+            }
+        }
+    }
+
+    /// Whether playback has run out of input.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_playback_matches_recording() {
+        let movie = Movie {
+            rom_crc32: 0,
+            dmg_mode: false,
+            cheats: vec![],
+            start: MovieStart::PowerOn,
+            state: vec![],
+            inputs: vec![],
+        };
+        let mut recording = MovieSession::new(movie, MovieMode::Recording);
+        let mut recorded = vec![];
+        for frame in 0..3u8 {
+            recording.input.set_values(frame, 0x0f - frame);
+            for _ in 0..CYCLES_PER_FRAME {
+                recorded.extend(recording.tick());
+            }
+        }
+        assert_eq!(recorded, vec![(0, 0x0f), (1, 0x0e), (2, 0x0d)]);
+
+        let mut playing = MovieSession::new(recording.movie, MovieMode::Playing);
+        let mut played = vec![];
+        while !playing.finished() {
+            played.extend(playing.tick());
+        }
+        assert_eq!(played, recorded);
+    }
+}
@@ -1,10 +1,22 @@
-use super::{Display, InterruptController, DEBUG};
+use super::{InterruptController, DEBUG};
 use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
-use sdl2::pixels::{Color, PixelFormatEnum};
-use sdl2::render::{Texture, TextureCreator};
-use sdl2::video::WindowContext;
+use nanoserde::{DeBin, SerBin};
 
-#[derive(Copy, Clone, Default)]
+/// White to black, as RGB555, for a DMG.
+const DMG_SHADES: [u16; 4] = [0x7fff, 0x56b5, 0x294a, 0x0000];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color { r: 0xff, g: 0xff, b: 0xff };
+}
+
+#[derive(Copy, Clone, Default, SerBin, DeBin)]
 struct BGMapAttributes {
     bg_oam_priority: bool,
     vertical_flip: bool,
@@ -45,7 +57,7 @@ impl BGMapAttributes {
     }
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, SerBin, DeBin)]
 pub struct LcdControl {
     display_enable: bool,
     window_tile_map_select: bool,
@@ -99,7 +111,7 @@ impl LcdControl {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, SerBin, DeBin)]
 pub struct LcdStatus {
     coincidence_interrupt_enable: bool,
     oam_interrupt_enable: bool,
@@ -151,6 +163,7 @@ impl LcdStatus {
     }
 }
 
+#[derive(SerBin, DeBin)]
 struct CgbPalette {
     auto_increment: bool,
     index: u8,
@@ -166,6 +179,15 @@ impl CgbPalette {
         }
     }
 
+    /// Sets the four colors of `palette`, as RGB555.
+    fn set_colors(&mut self, palette: usize, colors: [u16; 4]) {
+        for (shade, color) in colors.iter().enumerate() {
+            let address = palette * 8 + shade * 2;
+            self.palette[address] = *color as u8;
+            self.palette[address + 1] = (*color >> 8) as u8;
+        }
+    }
+
     fn read_index(&self) -> u8 {
         if self.auto_increment {
             self.index | 0x80
@@ -199,22 +221,21 @@ impl CgbPalette {
         let address = ((shade * 2) + (palette * 8)) as usize;
         let rgb = (self.palette[address + 1] as u16) << 8 | self.palette[address] as u16;
         let red = rgb & 0x001f;
-        let green = (rgb & 0x03d0) >> 5;
+        let green = (rgb & 0x03e0) >> 5;
         let blue = (rgb & 0x7c00) >> 10;
         let red_corrected = (red * 13 + green * 2 + blue) >> 1;
         let green_corrected = (green * 3 + blue) << 1;
         let blue_corrected = (red * 3 + green * 2 + blue * 11) >> 1;
-        Color::RGB(
-            red_corrected as u8,
-            green_corrected as u8,
-            blue_corrected as u8,
-        )
+        Color {
+            r: red_corrected as u8,
+            g: green_corrected as u8,
+            b: blue_corrected as u8,
+        }
     }
 }
 
-pub struct Ppu<'a> {
-    texture: Texture<'a>,
-    display: Display,
+#[derive(SerBin, DeBin)]
+pub struct Ppu {
     frame_buffer: [u8; SCREEN_HEIGHT * SCREEN_WIDTH * 3],
     tile_data_bank0: [u8; 0x1800],
     tile_data_bank1: [u8; 0x1800],
@@ -241,9 +262,10 @@ pub struct Ppu<'a> {
     pub dmg_mode: bool,
     object_priority_mode: bool,
     screen_cleared: bool,
+    frame_ready: bool,
 }
 
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Copy, Clone, SerBin, DeBin)]
 struct Sprite {
     y: u8,
     x: u8,
@@ -256,23 +278,9 @@ struct Sprite {
     cgb_palette: u8,
 }
 
-impl<'a> Ppu<'a> {
-    pub fn new(
-        display: Display,
-        texture_creator: &'a TextureCreator<WindowContext>,
-        dmg_mode: bool,
-    ) -> Self {
-        let texture = texture_creator
-            .create_texture_streaming(
-                PixelFormatEnum::RGB24,
-                SCREEN_WIDTH as u32,
-                SCREEN_HEIGHT as u32,
-            )
-            .expect("Failed to create texture");
-
-        Ppu {
-            texture,
-            display,
+impl Ppu {
+    pub fn new(dmg_mode: bool) -> Self {
+        let mut ppu = Ppu {
             frame_buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT * 3],
             tile_data_bank0: [0; 0x1800],
             tile_data_bank1: [0; 0x1800],
@@ -296,10 +304,23 @@ impl<'a> Ppu<'a> {
             ly_compare: 0,
             vram_bank_sel: 0,
             dmg_mode,
-            dmg_compatibility: false,
+            dmg_compatibility: dmg_mode,
             object_priority_mode: false,
             screen_cleared: false,
+            frame_ready: false,
+        };
+        if dmg_mode {
+            ppu.set_dmg_colors(DMG_SHADES, [DMG_SHADES, DMG_SHADES]);
         }
+        ppu
+    }
+
+    /// Sets the colors the DMG palette registers pick from, as RGB555: the
+    /// first CGB background palette and the first two object palettes.
+    pub fn set_dmg_colors(&mut self, background: [u16; 4], objects: [[u16; 4]; 2]) {
+        self.cgb_background_palette.set_colors(0, background);
+        self.cgb_sprite_palette.set_colors(0, objects[0]);
+        self.cgb_sprite_palette.set_colors(1, objects[1]);
     }
 
     pub fn execute_cycle(&mut self, interrupt_controller: &mut InterruptController) {
@@ -767,10 +788,17 @@ impl<'a> Ppu<'a> {
     }
 
     fn render_frame(&mut self) {
-        self.texture
-            .update(None, &self.frame_buffer, SCREEN_WIDTH * 3)
-            .expect("Failed to update texture");
-        self.display.render(&self.texture);
+        self.frame_ready = true;
+    }
+
+    /// Returns the RGB24 frame buffer once per completed frame.
+    pub fn take_frame(&mut self) -> Option<&[u8]> {
+        if self.frame_ready {
+            self.frame_ready = false;
+            Some(&self.frame_buffer)
+        } else {
+            None
+        }
     }
 }
 
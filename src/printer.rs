@@ -0,0 +1,346 @@
+//! Game Boy Printer on the end of the link cable, saving printouts as PNGs.
+//!
+//! The Game Boy clocks every byte. A packet is `88 33`, a command, a
+//! compression flag, a little-endian data length, the data, a checksum of
+//! everything from the command on, and two bytes the printer answers with
+//! `81` and its status.
+
+use super::interrupts::InterruptController;
+use super::screenshot;
+use super::serial::{LinkCable, SerialLink};
+use super::SCREEN_WIDTH;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0f;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_BUSY: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPRINTED: u8 = 0x08;
+
+/// Bytes in one band of 2 rows of 20 tiles.
+const BAND_LEN: usize = 640;
+/// The printer holds up to 9 bands, a whole screen.
+const BUFFER_LEN: usize = BAND_LEN * 9;
+
+/// Pixel rows fed for each unit of margin.
+const MARGIN_LINES: usize = 8;
+
+/// How long printing keeps the printer busy, in 8MHz link time.
+const PRINT_TIME: u64 = 4_000_000;
+
+const SHADES: [u8; 4] = [0xff, 0xaa, 0x55, 0x00];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+pub struct Printer {
+    rom_path: PathBuf,
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    /// Decompressed tile data waiting to be printed.
+    buffer: Vec<u8>,
+    status: u8,
+    /// Link time of the last byte and of the last print command.
+    time: u64,
+    print_time: Option<u64>,
+    /// Answer to the byte passed to `send`.
+    reply: u8,
+    /// RGB24 rows printed since the paper was last cut.
+    paper: Vec<u8>,
+}
+
+impl Printer {
+    /// Printouts are saved next to the ROM as `game-print001.png` and so on.
+    pub fn new<P: AsRef<Path>>(rom_path: P) -> Self {
+        Printer {
+            rom_path: rom_path.as_ref().to_path_buf(),
+            state: State::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: vec![],
+            checksum: 0,
+            received_checksum: 0,
+            buffer: vec![],
+            status: 0,
+            time: 0,
+            print_time: None,
+            reply: 0,
+            paper: vec![],
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let busy = self.print_time.is_some_and(|start| self.time - start < PRINT_TIME);
+        let full = if self.buffer.len() >= BUFFER_LEN { STATUS_FULL } else { 0 };
+        let unprinted = if self.buffer.is_empty() { 0 } else { STATUS_UNPRINTED };
+        self.status | full | unprinted | if busy { STATUS_BUSY } else { 0 }
+    }
+
+    /// Takes the next byte of a packet and returns the printer's answer.
+    fn receive_byte(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+        self.state = match self.state {
+            State::Magic1 if byte == 0x88 => State::Magic2,
+            State::Magic1 => State::Magic1,
+            State::Magic2 if byte == 0x33 => State::Command,
+            State::Magic2 => State::Magic1,
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                State::Compression
+            }
+            State::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthLow
+            }
+            State::LengthLow => {
+                self.length = byte as usize;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                self.length |= (byte as usize) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                if self.length > 0 { State::Data } else { State::ChecksumLow }
+            }
+            State::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.length { State::ChecksumLow } else { State::Data }
+            }
+            State::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                State::Alive
+            }
+            State::Alive => {
+                reply = 0x81;
+                if self.received_checksum == self.checksum {
+                    self.status &= !STATUS_CHECKSUM_ERROR;
+                    self.run_command();
+                } else {
+                    self.status |= STATUS_CHECKSUM_ERROR;
+                }
+                State::Status
+            }
+            State::Status => {
+                reply = self.status();
+                State::Magic1
+            }
+        };
+        reply
+    }
+
+    fn run_command(&mut self) {
+        match self.command {
+            INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            }
+            DATA => {
+                let data = if self.compressed { decompress(&self.data) } else { self.data.clone() };
+                let space = BUFFER_LEN.saturating_sub(self.buffer.len());
+                self.buffer.extend(data.into_iter().take(space));
+            }
+            PRINT if self.data.len() == 4 => {
+                let (sheets, margins, palette) = (self.data[0], self.data[1], self.data[2]);
+                self.print(sheets, margins >> 4, margins & 0x0f, palette);
+                self.buffer.clear();
+                self.print_time = Some(self.time);
+            }
+            STATUS | PRINT => {}
+            command => println!("Printer: unknown command {:02x}", command),
+        }
+    }
+
+    /// Prints the buffer `sheets` times. The paper is cut and saved after a
+    /// print with a margin after it, so images printed in several parts stay
+    /// in one file.
+    fn print(&mut self, sheets: u8, margin_before: u8, margin_after: u8, palette: u8) {
+        self.feed(margin_before as usize * MARGIN_LINES);
+        let image = render(&self.buffer, palette);
+        for _ in 0..sheets {
+            self.paper.extend_from_slice(&image);
+        }
+        self.feed(margin_after as usize * MARGIN_LINES);
+        if margin_after > 0 {
+            self.cut();
+        }
+    }
+
+    fn feed(&mut self, lines: usize) {
+        let length = self.paper.len() + lines * SCREEN_WIDTH * 3;
+        self.paper.resize(length, 0xff);
+    }
+
+    /// Saves the paper printed so far, if there is any.
+    fn cut(&mut self) {
+        if self.paper.iter().all(|&c| c == 0xff) {
+            self.paper.clear();
+            return;
+        }
+        let path = (1..)
+            .map(|number| print_path(&self.rom_path, number))
+            .find(|path| !path.exists())
+            .unwrap();
+        let height = self.paper.len() / (SCREEN_WIDTH * 3);
+        match screenshot::save_png(&path, SCREEN_WIDTH, height, &self.paper) {
+            Ok(()) => println!("Printed {}", path.display()),
+            Err(e) => println!("Failed to save printout {}: {}", path.display(), e),
+        }
+        self.paper.clear();
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        self.cut();
+    }
+}
+
+impl LinkCable for Printer {
+    fn send(&mut self, byte: u8, _control: u8, time: u64) -> io::Result<()> {
+        self.time = time;
+        self.reply = self.receive_byte(byte);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<u8> {
+        Ok(self.reply)
+    }
+
+    // The printer never clocks a transfer itself
+    fn poll(&mut self, _serial: &mut SerialLink, _interrupts: &mut InterruptController, _time: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn print_path(rom_path: &Path, number: u32) -> PathBuf {
+    let stem = rom_path.file_stem().unwrap().to_string_lossy();
+    rom_path.with_file_name(format!("{}-print{:03}.png", stem, number))
+}
+
+/// Expands run-length encoded data: a control byte with bit 7 set repeats the
+/// next byte `(control & 0x7f) + 2` times, otherwise `control + 1` bytes
+/// follow as they are.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![];
+    let mut bytes = data.iter();
+    while let Some(&control) = bytes.next() {
+        if control & 0x80 != 0 {
+            if let Some(&byte) = bytes.next() {
+                output.extend(std::iter::repeat_n(byte, (control & 0x7f) as usize + 2));
+            }
+        } else {
+            output.extend(bytes.by_ref().take(control as usize + 1));
+        }
+    }
+    output
+}
+
+/// Converts whole bands of tiles, 20 to a row, to RGB24 rows. A zero palette
+/// is taken as the usual `e4`.
+fn render(buffer: &[u8], palette: u8) -> Vec<u8> {
+    let palette = if palette == 0 { 0xe4 } else { palette };
+    let height = buffer.len() / BAND_LEN * 16;
+    let mut image = Vec::with_capacity(height * SCREEN_WIDTH * 3);
+    for y in 0..height {
+        for x in 0..SCREEN_WIDTH {
+            let tile = (y / 8) * 20 + x / 8;
+            let offset = tile * 16 + (y % 8) * 2;
+            let bit = 7 - x % 8;
+            let color = (buffer[offset] >> bit) & 1 | ((buffer[offset + 1] >> bit) & 1) << 1;
+            let shade = SHADES[(palette >> (color * 2)) as usize & 0x03];
+            image.extend_from_slice(&[shade, shade, shade]);
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn packet(command: u8, compressed: bool, data: &[u8]) -> Vec<u8> {
+        let mut body = vec![command, compressed as u8, data.len() as u8, (data.len() >> 8) as u8];
+        body.extend_from_slice(data);
+        let checksum = body.iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        let mut packet = vec![0x88, 0x33];
+        packet.extend(body);
+        packet.extend_from_slice(&[checksum as u8, (checksum >> 8) as u8, 0, 0]);
+        packet
+    }
+
+    fn send_packet(printer: &mut Printer, packet: &[u8]) -> Vec<u8> {
+        packet
+            .iter()
+            .map(|&byte| {
+                printer.send(byte, 0x81, 0).unwrap();
+                printer.receive().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(decompress(&[0x81, 0xaa, 0x01, 0x12, 0x34]), vec![0xaa, 0xaa, 0xaa, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_print() {
+        let rom_name = format!("gameboy_test_print_{}.gb", std::process::id());
+        let rom_path = std::env::temp_dir().join(rom_name);
+        let mut printer = Printer::new(&rom_path);
+
+        let replies = send_packet(&mut printer, &packet(INIT, false, &[]));
+        assert_eq!(&replies[replies.len() - 2..], &[0x81, 0x00]);
+
+        // One band of color 3, compressed
+        let band = [0xff, 0xff].repeat(BAND_LEN / 2);
+        let data = [0x80 | 126, 0xff].repeat(BAND_LEN / 128);
+        assert_eq!(decompress(&data), band);
+        let replies = send_packet(&mut printer, &packet(DATA, true, &data));
+        assert_eq!(replies.last(), Some(&STATUS_UNPRINTED));
+
+        let mut bad = packet(STATUS, false, &[]);
+        bad[6] ^= 1;
+        assert_eq!(send_packet(&mut printer, &bad).last(), Some(&(STATUS_CHECKSUM_ERROR | STATUS_UNPRINTED)));
+
+        let replies = send_packet(&mut printer, &packet(PRINT, false, &[1, 0x10, 0xe4, 0x40]));
+        assert_eq!(replies.last(), Some(&STATUS_BUSY));
+        assert_eq!(printer.paper.len(), (16 + MARGIN_LINES) * SCREEN_WIDTH * 3);
+
+        printer.cut();
+        let path = print_path(&rom_path, 1);
+        let printed = std::fs::remove_file(&path);
+        assert!(printed.is_ok(), "{} wasn't written", path.display());
+    }
+}
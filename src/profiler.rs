@@ -0,0 +1,164 @@
+use super::call_stack::CallStack;
+use super::debugger::rom_bank;
+use super::symbols::Symbols;
+use super::Cpu;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// A PC with the ROM bank mapped there, or `None` outside cartridge ROM.
+type Location = (Option<usize>, usize);
+
+/// The call sites and targets of a call stack, outermost first.
+type Stack = Vec<(Location, Location)>;
+
+/// Counts the T-cycles spent on each instruction, keyed by banked PC and the
+/// call stack it ran under. HALT time counts towards the HALT instruction.
+#[derive(Default)]
+pub struct Profiler {
+    call_stack: CallStack,
+    stacks: Vec<Stack>,
+    stack_ids: HashMap<Stack, usize>,
+    stack_id: usize,
+    cycles: HashMap<(usize, Location), u64>,
+    current: Option<(usize, Location)>,
+    last_cycle: u32,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let mut profiler = Profiler::default();
+        profiler.intern_stack();
+        profiler
+    }
+
+    /// Call at every instruction start with the emulator's cycle counter.
+    pub fn instruction_start(&mut self, cpu: &Cpu, cycle: u32) {
+        if let Some(current) = self.current {
+            *self.cycles.entry(current).or_insert(0) += cycle.wrapping_sub(self.last_cycle) as u64;
+        }
+
+        let depth = self.call_stack.frames().len();
+        let innermost = self.call_stack.frames().last().copied();
+        self.call_stack.update(cpu);
+        if self.call_stack.frames().len() != depth || self.call_stack.frames().last().copied() != innermost {
+            self.intern_stack();
+        }
+
+        let pc = cpu.debug_pc();
+        self.current = Some((self.stack_id, (rom_bank(cpu, pc), pc)));
+        self.last_cycle = cycle;
+    }
+
+    fn intern_stack(&mut self) {
+        let stack: Stack = self
+            .call_stack
+            .frames()
+            .iter()
+            .map(|frame| ((frame.call_bank, frame.call_site), (frame.target_bank, frame.target)))
+            .collect();
+        let next_id = self.stacks.len();
+        self.stack_id = *self.stack_ids.entry(stack.clone()).or_insert(next_id);
+        if self.stack_id == next_id {
+            self.stacks.push(stack);
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.cycles.values().sum()
+    }
+
+    /// Cycles per location, most expensive first.
+    fn by_address(&self) -> Vec<(Location, u64)> {
+        let mut totals: HashMap<Location, u64> = HashMap::new();
+        for (&(_, location), &cycles) in self.cycles.iter() {
+            *totals.entry(location).or_insert(0) += cycles;
+        }
+        sorted(totals)
+    }
+
+    /// Cycles per label containing the PC, most expensive first.
+    fn by_function<'a>(&self, symbols: &'a Symbols) -> Vec<(&'a str, u64)> {
+        let mut totals: HashMap<&str, u64> = HashMap::new();
+        for (&(_, (bank, pc)), &cycles) in self.cycles.iter() {
+            let name = symbols.containing(bank, pc).map_or("(no label)", |(name, _)| name);
+            *totals.entry(name).or_insert(0) += cycles;
+        }
+        sorted(totals)
+    }
+
+    /// Writes per-function totals (if there are symbols) and per-address
+    /// totals, sorted by cycles.
+    pub fn write_report(&self, out: &mut dyn Write, symbols: &Symbols) -> io::Result<()> {
+        let total = self.total().max(1);
+        let percent = |cycles: u64| cycles as f64 * 100.0 / total as f64;
+        writeln!(out, "Total: {} T-cycles", self.total())?;
+
+        if !symbols.is_empty() {
+            writeln!(out, "\n{:>12} {:>7}  function", "cycles", "%")?;
+            for (name, cycles) in self.by_function(symbols) {
+                writeln!(out, "{:>12} {:>6.2}%  {}", cycles, percent(cycles), name)?;
+            }
+        }
+
+        writeln!(out, "\n{:>12} {:>7}  address", "cycles", "%")?;
+        for ((bank, pc), cycles) in self.by_address() {
+            let label = symbols.describe(bank, pc).map(|label| format!("  {}", label)).unwrap_or_default();
+            writeln!(out, "{:>12} {:>6.2}%  {}{}", cycles, percent(cycles), format_location((bank, pc)), label)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `outer;inner;leaf cycles` lines for flamegraph.pl or inferno.
+    /// Frames are named by label, or by address without symbols.
+    pub fn write_folded(&self, out: &mut dyn Write, symbols: &Symbols) -> io::Result<()> {
+        let name = |location: Location, entry: Option<Location>| match symbols.containing(location.0, location.1) {
+            Some((name, _)) => name.to_string(),
+            None => entry.map_or_else(|| "(top)".to_string(), format_location),
+        };
+
+        let mut folded: HashMap<String, u64> = HashMap::new();
+        for (&(stack_id, pc), &cycles) in self.cycles.iter() {
+            let stack = &self.stacks[stack_id];
+            // Each call site is in its caller; the entries are the call targets
+            let mut names = vec![];
+            for (i, (call_site, _)) in stack.iter().enumerate() {
+                let entry = if i == 0 { None } else { Some(stack[i - 1].1) };
+                names.push(name(*call_site, entry));
+            }
+            names.push(name(pc, stack.last().map(|(_, target)| *target)));
+            *folded.entry(names.join(";")).or_insert(0) += cycles;
+        }
+
+        for (line, cycles) in sorted(folded) {
+            writeln!(out, "{} {}", line, cycles)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_report<P: AsRef<Path>>(&self, path: P, symbols: &Symbols) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_report(&mut writer, symbols)?;
+        writer.flush()
+    }
+
+    pub fn save_folded<P: AsRef<Path>>(&self, path: P, symbols: &Symbols) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_folded(&mut writer, symbols)?;
+        writer.flush()
+    }
+}
+
+fn sorted<K: Ord>(totals: HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut totals: Vec<(K, u64)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+fn format_location((bank, address): Location) -> String {
+    match bank {
+        Some(bank) => format!("{:02x}:{:04x}", bank, address),
+        None => format!("{:04x}", address),
+    }
+}
@@ -0,0 +1,168 @@
+use super::mmu::Mmu;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Region {
+    Wram,
+    Hram,
+    CartRam,
+}
+
+/// A searched byte, as an offset into all banks of its region.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RamAddress {
+    pub region: Region,
+    pub offset: usize,
+}
+
+impl RamAddress {
+    /// The bank and CPU address this byte is mapped at.
+    pub fn location(&self) -> (usize, usize) {
+        match self.region {
+            Region::Wram if self.offset < 0x1000 => (0, 0xc000 + self.offset),
+            Region::Wram => (self.offset / 0x1000, 0xd000 + self.offset % 0x1000),
+            Region::Hram => (0, 0xff80 + self.offset),
+            Region::CartRam => (self.offset / 0x2000, 0xa000 + self.offset % 0x2000),
+        }
+    }
+
+    /// GameShark code writing `value` here. WRAM banks above 1 use the `9x`
+    /// type; everything else is written through the current mapping.
+    pub fn gameshark_code(&self, value: u8) -> String {
+        let (bank, address) = self.location();
+        let code_type = match self.region {
+            Region::Wram if bank > 1 => 0x90 | bank as u8,
+            _ => 0x01,
+        };
+        format!("{:02X}{:02X}{:02X}{:02X}", code_type, value, address & 0xff, address >> 8)
+    }
+}
+
+impl fmt::Display for RamAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (bank, address) = self.location();
+        write!(f, "{:02x}:{:04x}", bank, address)
+    }
+}
+
+/// How each candidate's value must relate to the previous snapshot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Comparison {
+    /// Equal to the given value, or unchanged if there is none.
+    Equal(Option<u8>),
+    Changed,
+    Increased,
+    Decreased,
+}
+
+impl Comparison {
+    fn matches(&self, previous: u8, current: u8) -> bool {
+        match *self {
+            Comparison::Equal(Some(value)) => current == value,
+            Comparison::Equal(None) => current == previous,
+            Comparison::Changed => current != previous,
+            Comparison::Increased => current > previous,
+            Comparison::Decreased => current < previous,
+        }
+    }
+}
+
+/// Copies of the searchable memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub wram: Vec<u8>,
+    pub hram: Vec<u8>,
+    pub cart_ram: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn take(mmu: &Mmu) -> Self {
+        Snapshot {
+            wram: mmu.wram().to_vec(),
+            hram: mmu.hram().to_vec(),
+            cart_ram: mmu.cartridge.ram().to_vec(),
+        }
+    }
+
+    pub fn get(&self, address: RamAddress) -> u8 {
+        match address.region {
+            Region::Wram => self.wram[address.offset],
+            Region::Hram => self.hram[address.offset],
+            Region::CartRam => self.cart_ram[address.offset],
+        }
+    }
+}
+
+/// Narrows every searchable byte down to the ones that changed the way the
+/// user expects, comparing against the previous snapshot each time.
+pub struct RamSearch {
+    snapshot: Snapshot,
+    candidates: Vec<RamAddress>,
+}
+
+impl RamSearch {
+    pub fn new(snapshot: Snapshot) -> Self {
+        let regions = [
+            (Region::Wram, snapshot.wram.len()),
+            (Region::Hram, snapshot.hram.len()),
+            (Region::CartRam, snapshot.cart_ram.len()),
+        ];
+        let candidates = regions
+            .iter()
+            .flat_map(|&(region, length)| (0..length).map(move |offset| RamAddress { region, offset }))
+            .collect();
+        RamSearch { snapshot, candidates }
+    }
+
+    /// Keeps the candidates matching `comparison` and makes `current` the
+    /// snapshot for the next comparison.
+    pub fn narrow(&mut self, current: Snapshot, comparison: Comparison) {
+        let previous = &self.snapshot;
+        self.candidates
+            .retain(|&address| comparison.matches(previous.get(address), current.get(address)));
+        self.snapshot = current;
+    }
+
+    pub fn candidates(&self) -> &[RamAddress] {
+        &self.candidates
+    }
+
+    /// The value at `address` when the last snapshot was taken.
+    pub fn value(&self, address: RamAddress) -> u8 {
+        self.snapshot.get(address)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snapshot(wram: &[u8]) -> Snapshot {
+        Snapshot { wram: wram.to_vec(), hram: vec![0; 2], cart_ram: vec![] }
+    }
+
+    #[test]
+    fn test_narrow() {
+        let mut search = RamSearch::new(snapshot(&[3, 3, 3, 9]));
+        assert_eq!(search.candidates().len(), 6);
+
+        search.narrow(snapshot(&[2, 3, 4, 9]), Comparison::Changed);
+        assert_eq!(search.candidates().len(), 2);
+
+        search.narrow(snapshot(&[1, 3, 5, 9]), Comparison::Decreased);
+        assert_eq!(search.candidates(), &[RamAddress { region: Region::Wram, offset: 0 }]);
+        assert_eq!(search.value(search.candidates()[0]), 1);
+
+        search.narrow(snapshot(&[1, 3, 5, 9]), Comparison::Equal(Some(2)));
+        assert!(search.candidates().is_empty());
+    }
+
+    #[test]
+    fn test_gameshark_code() {
+        let wram = |offset| RamAddress { region: Region::Wram, offset };
+        assert_eq!(wram(0x0a63).gameshark_code(0x03), "010363CA");
+        assert_eq!(wram(0x10a2).gameshark_code(0x63), "0163A2D0");
+        assert_eq!(wram(0x20a2).gameshark_code(0x63), "9263A2D0");
+        assert_eq!(RamAddress { region: Region::Hram, offset: 1 }.to_string(), "00:ff81");
+    }
+}
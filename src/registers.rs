@@ -1,6 +1,7 @@
 use std::fmt;
+use nanoserde::{DeBin, SerBin};
 
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone, Debug, SerBin, DeBin)]
 pub struct Flags {
     pub z: bool,
     pub n: bool,
@@ -26,7 +27,7 @@ impl Flags {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, SerBin, DeBin)]
 pub struct Registers {
     pub a: u8,
     pub b: u8,
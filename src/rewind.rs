@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+
+/// Frames between snapshots.
+pub const SNAPSHOT_INTERVAL: u32 = 4;
+
+/// Only every Nth stereo sample is kept for reverse playback.
+const AUDIO_DECIMATION: usize = 4;
+
+/// An older snapshot, stored as the difference to the snapshot after it.
+struct Delta {
+    data: Vec<u8>,
+    audio: Vec<i16>,
+}
+
+/// Ring buffer of machine snapshots for rewinding.
+///
+/// Only the newest snapshot is kept whole. Each older one is stored as a
+/// run-length encoded XOR against its successor, which is mostly zeros since
+/// little of the machine changes in a few frames. Each snapshot also carries
+/// the audio played after it, so that rewinding can play it backwards.
+pub struct Rewind {
+    latest: Option<(Vec<u8>, Vec<i16>)>,
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+    frame_count: u32,
+}
+
+impl Rewind {
+    pub fn new(seconds: u32) -> Self {
+        Rewind {
+            latest: None,
+            deltas: VecDeque::new(),
+            capacity: (seconds * 60 / SNAPSHOT_INTERVAL) as usize,
+            frame_count: 0,
+        }
+    }
+
+    /// Counts a completed frame and returns whether a snapshot is due.
+    pub fn frame_done(&mut self) -> bool {
+        self.frame_count = (self.frame_count + 1) % SNAPSHOT_INTERVAL;
+        self.frame_count == 0
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some((previous, audio)) = self.latest.take() {
+            self.deltas.push_back(Delta { data: compress(&previous, &state), audio });
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = Some((state, vec![]));
+    }
+
+    /// Keeps a decimated copy of audio played since the newest snapshot.
+    pub fn record_audio(&mut self, samples: &[f32]) {
+        if let Some((_, audio)) = self.latest.as_mut() {
+            for pair in samples.chunks(2 * AUDIO_DECIMATION) {
+                audio.extend(pair[0..2].iter().map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16));
+            }
+        }
+    }
+
+    /// Removes the newest snapshot, returning it and the audio that followed
+    /// it, reversed and ready to queue.
+    pub fn pop(&mut self) -> Option<(Vec<u8>, Vec<f32>)> {
+        let (state, audio) = self.latest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.latest = Some((decompress(&delta.data, &state), delta.audio));
+        }
+
+        let reversed = audio
+            .chunks(2)
+            .rev()
+            .flat_map(|pair| pair.repeat(AUDIO_DECIMATION))
+            .map(|s| s as f32 / i16::MAX as f32)
+            .collect();
+        Some((state, reversed))
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.frame_count = 0;
+    }
+}
+
+fn push_len(output: &mut Vec<u8>, len: usize) {
+    output.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn read_len(data: &[u8], index: &mut usize) -> usize {
+    let len = u32::from_le_bytes([data[*index], data[*index + 1], data[*index + 2], data[*index + 3]]);
+    *index += 4;
+    len as usize
+}
+
+/// Encodes `old` relative to `new` as alternating runs of unchanged bytes
+/// (a count) and changed bytes (a count followed by `old ^ new`).
+fn compress(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let xor = |i: usize| old[i] ^ new.get(i).copied().unwrap_or(0);
+    let mut output = vec![];
+    push_len(&mut output, old.len());
+
+    let mut i = 0;
+    while i < old.len() {
+        let start = i;
+        while i < old.len() && xor(i) == 0 {
+            i += 1;
+        }
+        push_len(&mut output, i - start);
+
+        let start = i;
+        while i < old.len() && xor(i) != 0 {
+            i += 1;
+        }
+        push_len(&mut output, i - start);
+        output.extend((start..i).map(xor));
+    }
+    output
+}
+
+fn decompress(data: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut index = 0;
+    let len = read_len(data, &mut index);
+    let mut old = new.to_vec();
+    old.resize(len, 0);
+
+    let mut i = 0;
+    while i < len {
+        i += read_len(data, &mut index);
+        let changed = read_len(data, &mut index);
+        for byte in &mut old[i..i + changed] {
+            *byte ^= data[index];
+            index += 1;
+        }
+        i += changed;
+    }
+    old
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let new: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let mut old = new.clone();
+        old[0] = 0xff;
+        old[500..510].fill(0);
+        assert_eq!(decompress(&compress(&old, &new), &new), old);
+        assert_eq!(decompress(&compress(&old[0..600], &new), &new), &old[0..600]);
+        assert_eq!(decompress(&compress(&old, &new[0..600]), &new[0..600]), old);
+    }
+
+    #[test]
+    fn test_rewind_order() {
+        let mut rewind = Rewind::new(1);
+        for i in 0..20u8 {
+            rewind.push(vec![i; 100]);
+            rewind.record_audio(&[i as f32 / 100.0; 2 * AUDIO_DECIMATION]);
+        }
+
+        // Capacity is 15 deltas plus the latest snapshot
+        for i in (4..20u8).rev() {
+            let (state, audio) = rewind.pop().unwrap();
+            assert_eq!(state, vec![i; 100]);
+            assert_eq!(audio.len(), 2 * AUDIO_DECIMATION);
+        }
+        assert!(rewind.pop().is_none());
+    }
+}
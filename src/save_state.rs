@@ -0,0 +1,121 @@
+use nanoserde::{DeBin, DeBinErr, SerBin};
+use std::fmt;
+use std::io;
+
+const MAGIC: [u8; 8] = *b"GBSTATE\0";
+
+/// Bump whenever the serialized layout of any component changes, so that
+/// states from older builds are rejected instead of loaded as garbage.
+pub const VERSION: u32 = 9;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    NotASaveState,
+    UnsupportedVersion(u32),
+    WrongRom,
+    WrongBootRom,
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::Io(e) => write!(f, "{}", e),
+            SaveStateError::NotASaveState => write!(f, "not a save state file"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported (expected version {})",
+                version, VERSION
+            ),
+            SaveStateError::WrongRom => write!(f, "save state was made with a different ROM"),
+            SaveStateError::WrongBootRom => {
+                write!(f, "save state was made while running a boot ROM that isn't loaded")
+            }
+            SaveStateError::Corrupt(e) => write!(f, "save state is corrupt: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl From<io::Error> for SaveStateError {
+    fn from(e: io::Error) -> Self {
+        SaveStateError::Io(e)
+    }
+}
+
+impl From<DeBinErr> for SaveStateError {
+    fn from(e: DeBinErr) -> Self {
+        SaveStateError::Corrupt(e.to_string())
+    }
+}
+
+/// `rom_crc32` is the CRC-32 of the whole ROM. `boot_rom` is the CRC-32 of
+/// the boot ROM if it is still mapped, since the state can only be resumed
+/// with that same boot ROM.
+pub fn write_header(output: &mut Vec<u8>, rom_crc32: u32, boot_rom: Option<u32>) {
+    output.extend_from_slice(&MAGIC);
+    VERSION.ser_bin(output);
+    rom_crc32.ser_bin(output);
+    boot_rom.ser_bin(output);
+}
+
+/// `boot_rom` is the CRC-32 of the boot ROM of the machine loading the state.
+pub fn read_header(
+    offset: &mut usize,
+    data: &[u8],
+    rom_crc32: u32,
+    boot_rom: Option<u32>,
+) -> Result<(), SaveStateError> {
+    if data.len() < MAGIC.len() || data[0..MAGIC.len()] != MAGIC {
+        return Err(SaveStateError::NotASaveState);
+    }
+    *offset = MAGIC.len();
+
+    let version: u32 = DeBin::de_bin(offset, data)?;
+    if version != VERSION {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+
+    let state_rom_crc32: u32 = DeBin::de_bin(offset, data)?;
+    if state_rom_crc32 != rom_crc32 {
+        return Err(SaveStateError::WrongRom);
+    }
+
+    let state_boot_rom: Option<u32> = DeBin::de_bin(offset, data)?;
+    if state_boot_rom.is_some() && state_boot_rom != boot_rom {
+        return Err(SaveStateError::WrongBootRom);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_header() {
+        let mut data = vec![];
+        write_header(&mut data, 0x1234_5678, None);
+        let mut offset = 0;
+        assert!(read_header(&mut offset, &data, 0x1234_5678, None).is_ok());
+        assert_eq!(offset, data.len());
+        assert!(read_header(&mut 0, &data, 0x1234_5678, Some(0x1234)).is_ok());
+
+        assert!(matches!(read_header(&mut 0, &data, 0x1234_5679, None), Err(SaveStateError::WrongRom)));
+        assert!(matches!(read_header(&mut 0, b"not a state", 0x1234_5678, None), Err(SaveStateError::NotASaveState)));
+
+        let mut booting = vec![];
+        write_header(&mut booting, 0x1234_5678, Some(0x1234));
+        assert!(read_header(&mut 0, &booting, 0x1234_5678, Some(0x1234)).is_ok());
+        assert!(matches!(read_header(&mut 0, &booting, 0x1234_5678, None), Err(SaveStateError::WrongBootRom)));
+        assert!(matches!(read_header(&mut 0, &booting, 0x1234_5678, Some(0x4321)), Err(SaveStateError::WrongBootRom)));
+
+        data[MAGIC.len()] = 0xff;
+        assert!(matches!(read_header(&mut 0, &data, 0x1234_5678, None), Err(SaveStateError::UnsupportedVersion(_))));
+
+        assert!(matches!(read_header(&mut 0, &data[0..MAGIC.len() + 2], 0x1234_5678, None), Err(SaveStateError::Corrupt(_))));
+    }
+}
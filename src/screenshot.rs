@@ -0,0 +1,121 @@
+use super::linked::LINKED_SCREEN_WIDTH;
+use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// Writes an RGB24 image as a PNG.
+pub fn save_png<P: AsRef<Path>>(path: P, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    Ok(())
+}
+
+/// Writes a frame as returned by `Ppu::take_frame`, or a linked pair's
+/// combined frame, as a PNG.
+pub fn save_frame<P: AsRef<Path>>(path: P, frame: &[u8]) -> io::Result<()> {
+    save_png(path, frame.len() / (SCREEN_HEIGHT * 3), SCREEN_HEIGHT, frame)
+}
+
+/// Reads a PNG the size of one screen, or of a linked pair's two, and
+/// converts it to an RGB24 frame.
+pub fn load_frame<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let width = info.width as usize;
+    if (width != SCREEN_WIDTH && width != LINKED_SCREEN_WIDTH) || info.height as usize != SCREEN_HEIGHT {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected a {}x{} image, got {}x{}", SCREEN_WIDTH, SCREEN_HEIGHT, info.width, info.height),
+        ));
+    }
+
+    let frame = match info.color_type {
+        png::ColorType::Rgb => buffer,
+        png::ColorType::Rgba => buffer.chunks(4).flat_map(|p| p[0..3].to_vec()).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&g| vec![g, g, g]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|p| vec![p[0], p[0], p[0]]).collect(),
+        png::ColorType::Indexed => unreachable!("palette is expanded by the decoder"),
+    };
+    Ok(frame)
+}
+
+/// Compares two RGB24 frames pixel by pixel. If they differ, returns the number
+/// of mismatched pixels and a diff image with mismatches in red over a dimmed
+/// copy of `actual`.
+pub fn diff_frames(actual: &[u8], expected: &[u8]) -> Option<(usize, Vec<u8>)> {
+    let mut mismatches = 0;
+    let mut diff = Vec::with_capacity(actual.len());
+    for (a, e) in actual.chunks(3).zip(expected.chunks(3)) {
+        if a == e {
+            diff.extend(a.iter().map(|c| c / 4));
+        } else {
+            mismatches += 1;
+            diff.extend(&[0xff, 0x00, 0x00]);
+        }
+    }
+
+    if mismatches == 0 {
+        None
+    } else {
+        Some((mismatches, diff))
+    }
+}
+
+/// Compares `frame` against the PNG at `reference`. On mismatch, writes the
+/// actual frame and a diff image next to the reference as `.actual.png` and
+/// `.diff.png`, and returns the number of mismatched pixels.
+pub fn compare_reference<P: AsRef<Path>>(reference: P, frame: &[u8]) -> io::Result<Option<usize>> {
+    let reference = reference.as_ref();
+    let expected = load_frame(reference)?;
+    if frame.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no frame was rendered"));
+    }
+    if frame.len() != expected.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame and reference sizes differ"));
+    }
+
+    match diff_frames(frame, &expected) {
+        None => Ok(None),
+        Some((mismatches, diff)) => {
+            save_frame(reference.with_extension("actual.png"), frame)?;
+            save_frame(reference.with_extension("diff.png"), &diff)?;
+            Ok(Some(mismatches))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff_frames() {
+        let expected = vec![0x40; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+        assert!(diff_frames(&expected, &expected).is_none());
+
+        let mut actual = expected.clone();
+        actual[3] = 0x00;
+        let (mismatches, diff) = diff_frames(&actual, &expected).unwrap();
+        assert_eq!(mismatches, 1);
+        assert_eq!(&diff[0..6], &[0x10, 0x10, 0x10, 0xff, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let frame: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT * 3).map(|i| i as u8).collect();
+        let path = std::env::temp_dir().join("gameboy_test_png_round_trip.png");
+        save_frame(&path, &frame).unwrap();
+        assert_eq!(load_frame(&path).unwrap(), frame);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use super::{Display, Input};
+use gameboy::frontend::{AudioSink, Controls, Frontend, InputSource, VideoSink};
+use gameboy::joypad::Joypad;
+use gameboy::{sound, SCREEN_HEIGHT};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::video::WindowContext;
+
+pub struct SdlFrontend<'a> {
+    display: Display,
+    texture: Texture<'a>,
+    width: usize,
+    audio_queue: AudioQueue<f32>,
+    input: Input,
+}
+
+impl<'a> SdlFrontend<'a> {
+    pub fn new(
+        sdl: &sdl2::Sdl,
+        display: Display,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        width: usize,
+    ) -> Self {
+        let texture = texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                width as u32,
+                SCREEN_HEIGHT as u32,
+            )
+            .expect("Failed to create texture");
+
+        let desired_spec = AudioSpecDesired {
+            freq: Some(sound::SAMPLE_RATE),
+            channels: Some(sound::CHANNELS),
+            samples: Some(1024),
+        };
+        let audio_queue = sdl.audio().unwrap().open_queue(None, &desired_spec).unwrap();
+
+        // Start playback
+        audio_queue.resume();
+
+        SdlFrontend {
+            display,
+            texture,
+            width,
+            audio_queue,
+            input: Input::new(sdl.event_pump().unwrap()),
+        }
+    }
+}
+
+impl<'a> VideoSink for SdlFrontend<'a> {
+    fn present_frame(&mut self, frame: &[u8]) {
+        self.texture
+            .update(None, frame, self.width * 3)
+            .expect("Failed to update texture");
+        self.display.render(&self.texture);
+    }
+}
+
+impl<'a> AudioSink for SdlFrontend<'a> {
+    fn audio_ready(&self) -> bool {
+        self.audio_queue.size() / 8 < 4096 * 3
+    }
+
+    fn queue_audio(&mut self, samples: &[f32]) {
+        self.audio_queue.queue_audio(samples).expect("Failed to queue audio");
+    }
+}
+
+impl<'a> InputSource for SdlFrontend<'a> {
+    fn poll_inputs(&mut self, joypad: &mut Joypad) -> Controls {
+        self.input.poll_inputs(joypad)
+    }
+}
+
+impl<'a> Frontend for SdlFrontend<'a> {
+    fn limit_speed(&self) -> bool {
+        true
+    }
+}
@@ -1,22 +1,85 @@
+use super::interrupts::InterruptController;
+use nanoserde::{DeBin, DeBinErr, SerBin};
+use std::io;
+
+/// 4MHz cycles per bit at 8192Hz, or at 262144Hz with the CGB fast clock.
+const SLOW_BIT_CYCLES: u32 = 512;
+const FAST_BIT_CYCLES: u32 = 16;
+
+/// The other end of the link cable. `time` counts emulated time at 8MHz,
+/// i.e. two per cycle at normal speed and one in double speed.
+pub trait LinkCable {
+    /// Starts a transfer clocked by this side, with the SC value that started it.
+    fn send(&mut self, byte: u8, control: u8, time: u64) -> io::Result<()>;
+
+    /// Returns the byte shifted in during the transfer passed to `send`, once
+    /// all 8 bits have been clocked. 0xff if the other side didn't answer.
+    fn receive(&mut self) -> io::Result<u8>;
+
+    /// Handles transfers clocked by the other side.
+    fn poll(&mut self, serial: &mut SerialLink, interrupts: &mut InterruptController, time: u64) -> io::Result<()>;
+}
 
 pub struct SerialLink {
-    byte_to_transfer: u8,
-    shift_clock: u8,
+    data: u8,
+    /// SC: bit 7 transfer in progress, bit 1 fast clock (CGB), bit 0 internal clock.
+    control: u8,
+    cgb: bool,
+    /// Cycles since an internally clocked transfer started.
+    cycles: u32,
+    /// Byte and SC of a transfer this side just started, for the link cable.
+    outgoing: Option<(u8, u8)>,
+    /// Every byte sent, once `capture_output` turns it on. Not saved in
+    /// states.
+    output: Option<Vec<u8>>,
+}
+
+impl SerBin for SerialLink {
+    fn ser_bin(&self, output: &mut Vec<u8>) {
+        self.data.ser_bin(output);
+        self.control.ser_bin(output);
+        self.cgb.ser_bin(output);
+        self.cycles.ser_bin(output);
+        self.outgoing.ser_bin(output);
+    }
+}
+
+impl DeBin for SerialLink {
+    fn de_bin(offset: &mut usize, data: &[u8]) -> Result<Self, DeBinErr> {
+        Ok(SerialLink {
+            data: DeBin::de_bin(offset, data)?,
+            control: DeBin::de_bin(offset, data)?,
+            cgb: DeBin::de_bin(offset, data)?,
+            cycles: DeBin::de_bin(offset, data)?,
+            outgoing: DeBin::de_bin(offset, data)?,
+            output: None,
+        })
+    }
+}
+
+impl Default for SerialLink {
+    fn default() -> Self {
+        Self::new(false)
+    }
 }
 
 impl SerialLink {
-    pub fn new() -> Self {
+    pub fn new(cgb: bool) -> Self {
         SerialLink {
-            byte_to_transfer: 0,
-            shift_clock: 0,
+            data: 0,
+            control: 0,
+            cgb,
+            cycles: 0,
+            outgoing: None,
+            output: None,
         }
     }
 
     pub fn read(&self, address: usize) -> u8 {
         if address == 0xff01 {
-            self.byte_to_transfer
+            self.data
         } else if address == 0xff02 {
-            0x7e | self.shift_clock
+            0x7c | self.control | if self.cgb { 0 } else { 0x02 }
         } else {
             0xff
         }
@@ -24,12 +87,136 @@ impl SerialLink {
 
     pub fn write(&mut self, address: usize, value: u8) {
         if address == 0xff01 {
-            self.byte_to_transfer = value;
+            self.data = value;
         } else if address == 0xff02 {
-            if value & 0x80 == 0x80 {
-                //print!("{}", self.byte_to_transfer as char);
+            self.control = value & if self.cgb { 0x83 } else { 0x81 };
+            if self.transferring() {
+                if let Some(output) = self.output.as_mut() {
+                    output.push(self.data);
+                }
+                if self.internal_clock() {
+                    self.cycles = 0;
+                    self.outgoing = Some((self.data, self.control));
+                }
             }
-            self.shift_clock = value & 0x01;
         }
     }
+
+    fn transferring(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    fn internal_clock(&self) -> bool {
+        self.control & 0x01 != 0
+    }
+
+    /// Runs at 4MHz, or 8MHz in double speed. Returns true when an internally
+    /// clocked transfer has shifted all 8 bits and needs `finish_transfer`.
+    pub fn execute_cycle(&mut self) -> bool {
+        if !self.transferring() || !self.internal_clock() {
+            return false;
+        }
+        let bit_cycles = if self.control & 0x02 != 0 { FAST_BIT_CYCLES } else { SLOW_BIT_CYCLES };
+        self.cycles += 1;
+        self.cycles == bit_cycles * 8
+    }
+
+    /// Takes the byte of a transfer this side started since the last call.
+    pub fn take_outgoing(&mut self) -> Option<(u8, u8)> {
+        self.outgoing.take()
+    }
+
+    /// Completes a transfer with the byte shifted in from the other side.
+    pub fn finish_transfer(&mut self, incoming: u8, interrupts: &mut InterruptController) {
+        self.data = incoming;
+        self.control &= !0x80;
+        interrupts.interrupt_flag |= 0x08;
+    }
+
+    /// Handles a whole byte clocked in by the other side. Returns the byte
+    /// shifted out, or `None` if this side wasn't waiting on an external
+    /// clock.
+    pub fn external_transfer(&mut self, incoming: u8, interrupts: &mut InterruptController) -> Option<u8> {
+        if !self.transferring() || self.internal_clock() {
+            return None;
+        }
+        let outgoing = self.data;
+        self.finish_transfer(incoming, interrupts);
+        Some(outgoing)
+    }
+
+    /// The byte a transfer clocked by the other side would shift out right
+    /// now, or `None` if this side isn't waiting on an external clock.
+    pub fn external_byte(&self) -> Option<u8> {
+        if self.transferring() && !self.internal_clock() {
+            Some(self.data)
+        } else {
+            None
+        }
+    }
+
+    /// Starts keeping every byte sent, for `take_output`.
+    pub fn capture_output(&mut self) {
+        self.output.get_or_insert_with(Vec::new);
+    }
+
+    pub fn capturing_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Returns the bytes sent since the last call, if capturing.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_internal_clock_transfer() {
+        let mut serial = SerialLink::new(false);
+        let mut interrupts = InterruptController::new();
+        serial.write(0xff01, 0x42);
+        serial.write(0xff02, 0x81);
+        assert_eq!(serial.take_outgoing(), Some((0x42, 0x81)));
+
+        let cycles = (1..).find(|_| serial.execute_cycle()).unwrap();
+        assert_eq!(cycles, 8 * SLOW_BIT_CYCLES);
+        serial.finish_transfer(0xff, &mut interrupts);
+        assert_eq!(serial.read(0xff01), 0xff);
+        assert_eq!(serial.read(0xff02), 0x7f);
+        assert_eq!(interrupts.interrupt_flag, 0x08);
+    }
+
+    #[test]
+    fn test_external_clock_transfer() {
+        let mut serial = SerialLink::new(true);
+        let mut interrupts = InterruptController::new();
+        serial.write(0xff01, 0x12);
+        assert_eq!(serial.external_transfer(0x34, &mut interrupts), None);
+
+        serial.write(0xff02, 0x80);
+        assert_eq!(serial.take_outgoing(), None);
+        assert!(!serial.execute_cycle());
+        assert_eq!(serial.external_transfer(0x34, &mut interrupts), Some(0x12));
+        assert_eq!(serial.read(0xff01), 0x34);
+        assert_eq!(interrupts.interrupt_flag, 0x08);
+    }
+
+    #[test]
+    fn test_output_capture() {
+        let mut serial = SerialLink::new(false);
+        serial.write(0xff01, 0x41);
+        serial.write(0xff02, 0x81);
+        assert_eq!(serial.take_output(), Vec::<u8>::new());
+
+        serial.capture_output();
+        serial.write(0xff02, 0x81);
+        let state = SerBin::serialize_bin(&serial);
+        assert_eq!(serial.take_output(), vec![0x41]);
+        let loaded: SerialLink = DeBin::deserialize_bin(&state).unwrap();
+        assert!(!loaded.capturing_output());
+    }
 }
@@ -1,8 +1,13 @@
-use sdl2::audio::AudioSpecDesired;
+use nanoserde::{DeBin, SerBin};
+
+pub const SAMPLE_RATE: i32 = 93207;
+pub const CHANNELS: u8 = 2;
 
 const OUTPUT_BUFFER_LEN: usize = 4096;
+const AUDIO_CHUNK_LEN: usize = 512;
 const SWEEP_OVERFLOW: u16 = 2047;
 
+#[derive(SerBin, DeBin)]
 struct ToneSweepChannel {
     dac_enabled: bool,
     enabled: bool,
@@ -236,6 +241,7 @@ impl ToneSweepChannel {
 
 
 
+#[derive(SerBin, DeBin)]
 struct WaveChannel {
     enabled: bool,
     dac_enabled: bool,
@@ -378,6 +384,7 @@ impl WaveChannel {
     }
 }
 
+#[derive(SerBin, DeBin)]
 struct NoiseChannel {
     dac_enabled: bool,
     enabled: bool,
@@ -547,9 +554,9 @@ impl NoiseChannel {
     }
 }
 
+#[derive(SerBin, DeBin)]
 pub struct SoundController {
     master_enable: bool,
-    audio_queue: sdl2::audio::AudioQueue<f32>,
     output_terminal_settings: u8,
     tone_sweep_channel: ToneSweepChannel,
     tone_channel: ToneSweepChannel,
@@ -569,25 +576,16 @@ pub struct SoundController {
     mute: bool,
 }
 
-impl SoundController {
-    pub fn new(sdl: &sdl2::Sdl) -> Self {
-
-        let sdl_audio = sdl.audio().unwrap();
-
-        let desired_spec = AudioSpecDesired {
-            freq: Some(93207),
-            channels: Some(2),  // stereo
-            samples: Some(1024),
-        };
-
-        let audio_queue = sdl_audio.open_queue(None, &desired_spec).unwrap();
-
-        // Start playback
-        audio_queue.resume();
+impl Default for SoundController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl SoundController {
+    pub fn new() -> Self {
         SoundController {
             master_enable: false,
-            audio_queue,
             output_terminal_settings: 0,
             tone_sweep_channel: ToneSweepChannel::new(),
             tone_channel: ToneSweepChannel::new(),
@@ -728,7 +726,7 @@ impl SoundController {
 
     pub fn tick_frame_sequencer(&mut self) {
         self.frame_sequence_count = (self.frame_sequence_count + 1) % 8;
-        if self.frame_sequence_count % 2 == 0 {
+        if self.frame_sequence_count.is_multiple_of(2) {
             self.tone_sweep_channel.tick_length_counter();
             self.tone_channel.tick_length_counter();
             self.wave_channel.tick_length_counter();
@@ -744,14 +742,16 @@ impl SoundController {
         }
     }
 
-    pub fn queue_audio(&mut self) {
-        let queue_insert_len = 512;
-        if self.audio_queue.size() / 8 < 4096 * 3 && self.buffer_size >= queue_insert_len {
+    /// Removes the next chunk of interleaved stereo samples, if one is ready.
+    pub fn take_audio(&mut self) -> Option<&[f32]> {
+        if self.buffer_size >= AUDIO_CHUNK_LEN {
             let start_index = self.buffer_remove_index;
-            let end_index = self.buffer_remove_index + queue_insert_len;
-            self.audio_queue.queue(&self.output_buffer[start_index..end_index]);
-            self.buffer_remove_index = (self.buffer_remove_index + queue_insert_len) % OUTPUT_BUFFER_LEN;
-            self.buffer_size -= queue_insert_len;
+            let end_index = self.buffer_remove_index + AUDIO_CHUNK_LEN;
+            self.buffer_remove_index = end_index % OUTPUT_BUFFER_LEN;
+            self.buffer_size -= AUDIO_CHUNK_LEN;
+            Some(&self.output_buffer[start_index..end_index])
+        } else {
+            None
         }
     }
 
@@ -0,0 +1,132 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Labels from an RGBDS `.sym` file, one `bank:address name` per line.
+///
+/// Lookups take the ROM bank for addresses in cartridge ROM and `None`
+/// elsewhere, in which case any bank matches.
+#[derive(Default)]
+pub struct Symbols {
+    by_location: BTreeMap<(usize, usize), String>,
+    by_address: BTreeMap<usize, String>,
+    by_name: HashMap<String, (usize, usize)>,
+}
+
+/// `game.gb` -> `game.sym`, as written by `rgblink -n`.
+pub fn symbol_path<P: AsRef<Path>>(rom_path: P) -> PathBuf {
+    rom_path.as_ref().with_extension("sym")
+}
+
+/// Labels only describe addresses in the same memory region.
+fn region(address: usize) -> usize {
+    match address {
+        0x0000..=0x3fff => 0,
+        0x4000..=0x7fff => 1,
+        0x8000..=0x9fff => 2,
+        0xa000..=0xbfff => 3,
+        0xc000..=0xdfff => 4,
+        0xff80..=0xfffe => 6,
+        _ => 5,
+    }
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Symbols::default();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let (location, name) = match (words.next(), words.next()) {
+                (Some(location), Some(name)) => (location, name),
+                _ => continue,
+            };
+            let (bank, address) = match location.split_once(':') {
+                Some((bank, address)) => (bank, address),
+                None => continue,
+            };
+            let (bank, address) = match (usize::from_str_radix(bank, 16), usize::from_str_radix(address, 16)) {
+                (Ok(bank), Ok(address)) => (bank, address),
+                _ => continue,
+            };
+
+            symbols.by_location.entry((bank, address)).or_insert_with(|| name.to_string());
+            symbols.by_address.entry(address).or_insert_with(|| name.to_string());
+            symbols.by_name.entry(name.to_string()).or_insert((bank, address));
+        }
+        symbols
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Symbols::parse(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// The label exactly at `address`.
+    pub fn name(&self, bank: Option<usize>, address: usize) -> Option<&str> {
+        match bank {
+            Some(bank) => self.by_location.get(&(bank, address)),
+            None => self.by_address.get(&address),
+        }
+        .map(|name| name.as_str())
+    }
+
+    /// `bank` and address of a label.
+    pub fn address_of(&self, name: &str) -> Option<(usize, usize)> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The closest label at or before `address`, and the offset from it.
+    pub fn containing(&self, bank: Option<usize>, address: usize) -> Option<(&str, usize)> {
+        let found = match bank {
+            Some(bank) => self.by_location.range((bank, 0)..=(bank, address)).next_back().map(|((_, a), n)| (*a, n)),
+            None => self.by_address.range(..=address).next_back().map(|(a, n)| (*a, n)),
+        };
+        found
+            .filter(|(label_address, _)| region(*label_address) == region(address))
+            .map(|(label_address, name)| (name.as_str(), address - label_address))
+    }
+
+    /// Formats `address` as `Label` or `Label+offset`, if any label covers it.
+    pub fn describe(&self, bank: Option<usize>, address: usize) -> Option<String> {
+        self.containing(bank, address).map(|(name, offset)| {
+            if offset == 0 {
+                name.to_string()
+            } else {
+                format!("{}+{:#x}", name, offset)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SYM: &str = "\
+; File generated by rgblink
+00:0150 Main
+00:0158 Main.loop
+01:4000 Banked
+02:4000 OtherBank
+00:c000 wCounter
+";
+
+    #[test]
+    fn test_lookup() {
+        let symbols = Symbols::parse(SYM);
+        assert_eq!(symbols.name(Some(0), 0x150), Some("Main"));
+        assert_eq!(symbols.name(Some(2), 0x4000), Some("OtherBank"));
+        assert_eq!(symbols.name(None, 0xc000), Some("wCounter"));
+        assert_eq!(symbols.address_of("Main.loop"), Some((0, 0x158)));
+
+        assert_eq!(symbols.describe(Some(0), 0x15a), Some("Main.loop+0x2".to_string()));
+        assert_eq!(symbols.describe(Some(1), 0x4010), Some("Banked+0x10".to_string()));
+        assert_eq!(symbols.describe(Some(0), 0x100), None);
+        // Labels don't extend into other regions
+        assert_eq!(symbols.describe(None, 0xff80), None);
+    }
+}
@@ -1,5 +1,7 @@
 use super::{DEBUG, InterruptController, SoundController};
+use nanoserde::{DeBin, SerBin};
 
+#[derive(SerBin, DeBin)]
 pub struct Timer {
     timer_counter: u8,
     timer_modulo: u8,
@@ -8,6 +10,12 @@ pub struct Timer {
     divider_tick: u16,
 }
 
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Timer {
     pub fn new() -> Self {
         Timer {
@@ -25,7 +33,7 @@ impl Timer {
         }
         self.divider_tick = self.divider_tick.wrapping_add(1);
 
-        if self.timer_enable && self.divider_tick % self.clock_div == 0 {
+        if self.timer_enable && self.divider_tick.is_multiple_of(self.clock_div) {
             self.timer_counter = self.timer_counter.wrapping_add(1);
             
             if DEBUG { println!("TAC: {}", self.timer_counter); }
@@ -37,6 +45,11 @@ impl Timer {
     }
 
 
+    /// Sets DIV, e.g. to where the boot ROM leaves it.
+    pub fn set_divider(&mut self, value: u8) {
+        self.divider_tick = (value as u16) << 8;
+    }
+
     pub fn write(&mut self, address: usize, value: u8) {
         match address {
             0xff04 => {
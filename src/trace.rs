@@ -0,0 +1,50 @@
+use super::debugger::rom_bank;
+use super::Cpu;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Logs CPU state before every instruction in the format used by Gameboy Doctor:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+///
+/// With `symbols` set and a ROM with symbols, lines inside a label end with
+/// ` ; Label+offset`, which Gameboy Doctor and plain diffs against its logs
+/// won't accept.
+pub struct Trace {
+    writer: BufWriter<File>,
+    symbols: bool,
+}
+
+impl Trace {
+    pub fn create<P: AsRef<Path>>(path: P, symbols: bool) -> io::Result<Self> {
+        Ok(Trace {
+            writer: BufWriter::new(File::create(path)?),
+            symbols,
+        })
+    }
+
+    pub fn log(&mut self, cpu: &Cpu) {
+        let pc = cpu.debug_pc();
+        let label = if self.symbols {
+            cpu.mmu.cartridge.symbols().describe(rom_bank(cpu, pc), pc)
+        } else {
+            None
+        };
+        match label {
+            Some(label) => writeln!(self.writer, "{} ; {}", format_line(cpu), label),
+            None => writeln!(self.writer, "{}", format_line(cpu)),
+        }
+        .expect("Failed to write trace");
+    }
+}
+
+pub fn format_line(cpu: &Cpu) -> String {
+    let r = &cpu.registers;
+    let pc = cpu.debug_pc();
+    let pc_mem = |i: usize| cpu.mmu.peek_byte((pc + i) & 0xffff);
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        r.a, r.f.as_u8(), r.b, r.c, r.d, r.e, r.h, r.l, cpu.sp, pc, pc_mem(0), pc_mem(1), pc_mem(2), pc_mem(3)
+    )
+}
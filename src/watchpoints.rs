@@ -0,0 +1,191 @@
+use super::debugger::parse_hex;
+use std::cell::Cell;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// A write that changes the stored value.
+    Change,
+}
+
+/// Watches an inclusive address range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Watchpoint {
+    /// Parses `[r|w|c:]start[-end]` with hex addresses. Watches writes if no
+    /// kind is given.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (kind, range) = match text.split_once(':') {
+            Some(("r", range)) => (WatchKind::Read, range),
+            Some(("w", range)) => (WatchKind::Write, range),
+            Some(("c", range)) => (WatchKind::Change, range),
+            Some(_) => return None,
+            None => (WatchKind::Write, text),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_hex(start)?, parse_hex(end)?),
+            None => (parse_hex(range)?, parse_hex(range)?),
+        };
+        if start > end || end > 0xffff {
+            return None;
+        }
+        Some(Watchpoint { kind, start, end })
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "r",
+            WatchKind::Write => "w",
+            WatchKind::Change => "c",
+        };
+        if self.start == self.end {
+            write!(f, "{}:{:04x}", kind, self.start)
+        } else {
+            write!(f, "{}:{:04x}-{:04x}", kind, self.start, self.end)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    pub index: usize,
+    pub watchpoint: Watchpoint,
+    pub address: usize,
+    /// The value read or written.
+    pub value: u8,
+    /// The value before a write.
+    pub old_value: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Watchpoint {} ({}): ", self.index, self.watchpoint)?;
+        match self.watchpoint.kind {
+            WatchKind::Read => write!(f, "read [{:04x}] = {:02x}", self.address, self.value),
+            WatchKind::Write => write!(f, "write [{:04x}] = {:02x} (was {:02x})", self.address, self.value, self.old_value),
+            WatchKind::Change => write!(f, "[{:04x}] changed {:02x} -> {:02x}", self.address, self.old_value, self.value),
+        }
+    }
+}
+
+/// Watchpoints checked by `Mmu` on every access. Reads only have `&self`, so
+/// the first hit is kept in a `Cell` until the emulator loop takes it.
+#[derive(Default)]
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    hit: Cell<Option<WatchHit>>,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.list.push(watchpoint);
+    }
+
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index < self.list.len() {
+            self.list.remove(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes the first watchpoint equal to `watchpoint`.
+    pub fn remove_matching(&mut self, watchpoint: Watchpoint) -> bool {
+        match self.list.iter().position(|w| *w == watchpoint) {
+            Some(index) => self.remove(index),
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> &[Watchpoint] {
+        &self.list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn take_hit(&self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+
+    fn record(&self, kind: WatchKind, address: usize, old_value: u8, value: u8) {
+        if self.hit.get().is_some() {
+            return;
+        }
+        let found = self
+            .list
+            .iter()
+            .enumerate()
+            .find(|(_, w)| w.kind == kind && w.contains(address));
+        if let Some((index, watchpoint)) = found {
+            self.hit.set(Some(WatchHit { index, watchpoint: *watchpoint, address, value, old_value }));
+        }
+    }
+
+    pub fn check_read(&self, address: usize, value: u8) {
+        self.record(WatchKind::Read, address, value, value);
+    }
+
+    /// `stored` is what reads back after the write, which for registers may
+    /// differ from `value`.
+    pub fn check_write(&self, address: usize, old_value: u8, value: u8, stored: u8) {
+        self.record(WatchKind::Write, address, old_value, value);
+        if stored != old_value {
+            self.record(WatchKind::Change, address, old_value, stored);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchpoint() {
+        assert_eq!(
+            Watchpoint::parse("c0a0"),
+            Some(Watchpoint { kind: WatchKind::Write, start: 0xc0a0, end: 0xc0a0 })
+        );
+        assert_eq!(
+            Watchpoint::parse("c:c000-c0ff"),
+            Some(Watchpoint { kind: WatchKind::Change, start: 0xc000, end: 0xc0ff })
+        );
+        assert_eq!(Watchpoint::parse("r:ff44").unwrap().to_string(), "r:ff44");
+        assert_eq!(Watchpoint::parse("x:ff44"), None);
+        assert_eq!(Watchpoint::parse("c0ff-c000"), None);
+    }
+
+    #[test]
+    fn test_watch_hits() {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add(Watchpoint::parse("c:c000-c0ff").unwrap());
+        watchpoints.add(Watchpoint::parse("r:c010").unwrap());
+
+        watchpoints.check_write(0xc020, 0x12, 0x12, 0x12);
+        assert_eq!(watchpoints.take_hit(), None);
+
+        watchpoints.check_write(0xc020, 0x12, 0x34, 0x34);
+        watchpoints.check_read(0xc010, 0x56);
+        let hit = watchpoints.take_hit().unwrap();
+        assert_eq!((hit.index, hit.address, hit.old_value, hit.value), (0, 0xc020, 0x12, 0x34));
+        assert_eq!(watchpoints.take_hit(), None);
+
+        watchpoints.check_read(0xc010, 0x56);
+        assert_eq!(watchpoints.take_hit().unwrap().index, 1);
+    }
+}
@@ -0,0 +1,57 @@
+//! Runs the ROMs in `$GAMEBOY_TEST_ROMS/acid2` (default `./test_roms/acid2`),
+//! e.g. dmg-acid2 and cgb-acid2, and compares the final frame against a PNG
+//! with the same name next to each ROM. `.gb` ROMs run in DMG mode, `.gbc`
+//! ROMs in CGB mode. Mismatches leave `.actual.png` and `.diff.png` files
+//! beside the reference.
+
+use gameboy::frontend::HeadlessFrontend;
+use gameboy::{screenshot, GameBoy, CYCLES_PER_FRAME};
+use std::path::PathBuf;
+
+const FRAMES: u64 = 60;
+
+#[test]
+fn acid2() {
+    let dir = std::env::var_os("GAMEBOY_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("test_roms"))
+        .join("acid2");
+    let mut roms: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "gb" || e == "gbc"))
+            .collect(),
+        Err(_) => vec![],
+    };
+    if roms.is_empty() {
+        println!("Skipping acid2: no ROMs in {}", dir.display());
+        return;
+    }
+    roms.sort();
+
+    let mut failures = vec![];
+    for rom in roms.iter() {
+        let dmg_mode = rom.extension().is_some_and(|e| e == "gb");
+        let mut game_boy = GameBoy::new(rom, dmg_mode, None);
+        let mut frontend = HeadlessFrontend::default();
+        for _ in 0..FRAMES * CYCLES_PER_FRAME as u64 {
+            game_boy.step(&mut frontend);
+        }
+
+        let reference = rom.with_extension("png");
+        match screenshot::compare_reference(&reference, &frontend.frame) {
+            Ok(None) => println!("PASS {}", rom.display()),
+            Ok(Some(mismatches)) => {
+                println!("FAIL {}: {} pixels differ", rom.display(), mismatches);
+                failures.push(rom.clone());
+            }
+            Err(e) => {
+                println!("FAIL {}: {}", rom.display(), e);
+                failures.push(rom.clone());
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "acid2 failed: {:?}", failures);
+}
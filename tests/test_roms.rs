@@ -0,0 +1,140 @@
+//! Runs the Blargg and Mooneye test ROMs found under `$GAMEBOY_TEST_ROMS`
+//! (default `./test_roms`), laid out as:
+//!
+//!   blargg/cpu_instrs/**/*.gb
+//!   blargg/instr_timing/**/*.gb
+//!   blargg/mem_timing/**/*.gb
+//!   mooneye/acceptance/**/*.gb
+//!
+//! Suites whose directory is missing are skipped.
+
+use gameboy::frontend::NullFrontend;
+use gameboy::{GameBoy, CYCLES_PER_FRAME};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+const BLARGG_FRAMES: u64 = 60 * 60;
+const MOONEYE_FRAMES: u64 = 60 * 20;
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pass,
+    Fail(String),
+}
+
+fn rom_dir() -> PathBuf {
+    std::env::var_os("GAMEBOY_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("test_roms"))
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|e| e == "gb" || e == "gbc") {
+            roms.push(path);
+        }
+    }
+}
+
+fn run_blargg(rom: &Path) -> Outcome {
+    let mut game_boy = GameBoy::new(rom, false, None);
+    game_boy.cpu.mmu.serial_link.capture_output();
+    let mut frontend = NullFrontend;
+    let mut output = vec![];
+
+    for cycle in 0..BLARGG_FRAMES * CYCLES_PER_FRAME as u64 {
+        game_boy.step(&mut frontend);
+        if cycle % CYCLES_PER_FRAME as u64 == 0 {
+            output.extend(game_boy.cpu.mmu.serial_link.take_output());
+            let text = String::from_utf8_lossy(&output);
+            if text.contains("Passed") {
+                return Outcome::Pass;
+            } else if text.contains("Failed") {
+                return Outcome::Fail(text.trim().to_string());
+            }
+        }
+    }
+
+    Outcome::Fail(format!(
+        "timed out, serial output: {:?}",
+        String::from_utf8_lossy(&output)
+    ))
+}
+
+fn run_mooneye(rom: &Path) -> Outcome {
+    let mut game_boy = GameBoy::new(rom, true, None);
+    let mut frontend = NullFrontend;
+
+    for _ in 0..MOONEYE_FRAMES * CYCLES_PER_FRAME as u64 {
+        game_boy.step(&mut frontend);
+
+        // The tests signal completion by executing LD B,B
+        if game_boy.cpu.opcode1 == 0x40 {
+            let r = &game_boy.cpu.registers;
+            let values = [r.b, r.c, r.d, r.e, r.h, r.l];
+            if values == MOONEYE_PASS {
+                return Outcome::Pass;
+            } else if values == MOONEYE_FAIL {
+                return Outcome::Fail("failure signalled by test".to_string());
+            }
+        }
+    }
+
+    Outcome::Fail("timed out".to_string())
+}
+
+fn run_suite(suite: &str, run_rom: fn(&Path) -> Outcome) {
+    let dir = rom_dir().join(suite);
+    let mut roms = vec![];
+    find_roms(&dir, &mut roms);
+    if roms.is_empty() {
+        println!("Skipping {}: no ROMs in {}", suite, dir.display());
+        return;
+    }
+
+    let mut failures = vec![];
+    for rom in roms.iter() {
+        let name = rom.strip_prefix(&dir).unwrap_or(rom).display().to_string();
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| run_rom(rom)))
+            .unwrap_or_else(|_| Outcome::Fail("emulator panicked".to_string()));
+        match outcome {
+            Outcome::Pass => println!("PASS {}", name),
+            Outcome::Fail(reason) => {
+                println!("FAIL {}: {}", name, reason);
+                failures.push(name);
+            }
+        }
+    }
+
+    println!("{}: {}/{} passed", suite, roms.len() - failures.len(), roms.len());
+    assert!(failures.is_empty(), "{} failed: {:?}", suite, failures);
+}
+
+#[test]
+fn blargg_cpu_instrs() {
+    run_suite("blargg/cpu_instrs", run_blargg);
+}
+
+#[test]
+fn blargg_instr_timing() {
+    run_suite("blargg/instr_timing", run_blargg);
+}
+
+#[test]
+fn blargg_mem_timing() {
+    run_suite("blargg/mem_timing", run_blargg);
+}
+
+#[test]
+fn mooneye_acceptance() {
+    run_suite("mooneye/acceptance", run_mooneye);
+}